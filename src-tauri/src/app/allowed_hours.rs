@@ -0,0 +1,110 @@
+use crate::app::config::PakeConfig;
+use crate::app::window::get_main_url;
+use crate::util::{get_next_allowed_start, is_within_allowed_hours};
+use chrono::Local;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Window};
+
+// Checked against the local clock now and then, so sleep, clock and time zone changes count.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// What the cover page itself needs, every other command is refused outside the allowed hours.
+pub const OUTSIDE_HOURS_COMMANDS: &[&str] = &[
+    "console_error",
+    "get_feature_flags",
+    "get_zoom",
+    "override_allowed_hours",
+    "shake",
+];
+pub const OUTSIDE_HOURS_MESSAGE: &str = "This app isn't available right now";
+
+static OUTSIDE_ALLOWED_HOURS: AtomicBool = AtomicBool::new(false);
+static ALLOWED_HOURS_OVERRIDDEN: AtomicBool = AtomicBool::new(false);
+
+pub fn is_outside_allowed_hours() -> bool {
+    OUTSIDE_ALLOWED_HOURS.load(Ordering::SeqCst) && !ALLOWED_HOURS_OVERRIDDEN.load(Ordering::SeqCst)
+}
+
+// Outside the allowed hours the main window is covered, and can go to the tray, until they start
+// again. Then the app starts over from its own url.
+pub fn watch_allowed_hours(app: AppHandle) {
+    if app.state::<PakeConfig>().allowed_hours.is_none() {
+        return;
+    }
+    // The first check happens before the page loads, so it is covered from the start.
+    check_allowed_hours(&app);
+    std::thread::spawn(move || loop {
+        std::thread::sleep(CHECK_INTERVAL);
+        if ALLOWED_HOURS_OVERRIDDEN.load(Ordering::SeqCst) {
+            return;
+        }
+        check_allowed_hours(&app);
+    });
+}
+
+fn check_allowed_hours(app: &AppHandle) {
+    let config = app.state::<PakeConfig>();
+    let allowed_hours = match &config.allowed_hours {
+        Some(allowed_hours) => allowed_hours,
+        None => return,
+    };
+    let outside = !is_within_allowed_hours(allowed_hours, Local::now().naive_local());
+    if OUTSIDE_ALLOWED_HOURS.swap(outside, Ordering::SeqCst) == outside {
+        return;
+    }
+    let window = match app.get_window("pake") {
+        Some(window) => window,
+        None => return,
+    };
+    if outside {
+        cover_page(&window);
+    } else {
+        let url = serde_json::to_string(get_main_url(&config)).unwrap();
+        let _res = window.eval(&format!("window.location.href = {};", url));
+    }
+
+    if !allowed_hours.hide_to_tray {
+        return;
+    }
+    #[cfg(not(target_os = "macos"))]
+    if !config.show_system_tray() {
+        return;
+    }
+    let _res = if outside {
+        window.hide()
+    } else {
+        window.show()
+    };
+}
+
+// Called again on every page load of the main window, so navigating doesn't uncover the page.
+pub fn cover_page(window: &Window) {
+    if window.label() != "pake" || !is_outside_allowed_hours() {
+        return;
+    }
+    let next_start = window
+        .state::<PakeConfig>()
+        .allowed_hours
+        .as_ref()
+        .and_then(|allowed_hours| get_next_allowed_start(allowed_hours, Local::now().naive_local()))
+        .map(|next_start| next_start.format("%Y-%m-%dT%H:%M:%S").to_string());
+    let _res = window.eval(&format!(
+        "window.pakeShowUnavailable && window.pakeShowUnavailable({});",
+        serde_json::to_string(&next_start).unwrap()
+    ));
+}
+
+// Lifts the allowed hours until the app quits.
+pub fn override_allowed_hours(config: &PakeConfig, passphrase: &str) -> Result<(), String> {
+    let expected = config
+        .allowed_hours
+        .as_ref()
+        .and_then(|allowed_hours| allowed_hours.override_passphrase.as_deref())
+        .ok_or("Allowed hours can't be overridden")?;
+    if passphrase != expected {
+        return Err("Wrong passphrase".to_string());
+    }
+    ALLOWED_HOURS_OVERRIDDEN.store(true, Ordering::SeqCst);
+    Ok(())
+}
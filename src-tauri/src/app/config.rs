@@ -1,6 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct WindowConfig {
     pub url: String,
     pub transparent: bool,
@@ -9,9 +10,87 @@ pub struct WindowConfig {
     pub height: f64,
     pub resizable: bool,
     pub url_type: String,
+    pub force_scale: Option<f64>,
+    pub window_tabbing: Option<WindowTabbing>,
+    // A directory, or "ask" to pick where each download goes.
+    pub download_path: Option<String>,
+    #[serde(default)]
+    pub always_on_top: bool,
+    // Size the window to the page content, for widget-style apps.
+    #[serde(default)]
+    pub auto_fit: bool,
+    pub min_width: Option<f64>,
+    pub min_height: Option<f64>,
+    // Title bar and borders, on by default.
+    pub decorations: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+// Below this most sites fall apart, widgets that fit their content go smaller.
+const DEFAULT_MIN_WIDTH: f64 = 480.0;
+const DEFAULT_MIN_HEIGHT: f64 = 360.0;
+
+impl WindowConfig {
+    pub fn min_size(&self) -> Option<(f64, f64)> {
+        if self.auto_fit && self.min_width.is_none() && self.min_height.is_none() {
+            return None;
+        }
+        Some((
+            self.min_width.unwrap_or(DEFAULT_MIN_WIDTH),
+            self.min_height.unwrap_or(DEFAULT_MIN_HEIGHT),
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowTabbing {
+    Default,
+    Preferred,
+    Disallowed,
+}
+
+// Focus outlines, many apps hide them for keyboard users too.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusRing {
+    Default,
+    Always,
+    KeyboardOnly,
+    Never,
+}
+
+// What Cmd+W, or Ctrl+W on Windows and Linux, does to the main window.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloseBehavior {
+    Minimize,
+    Hide,
+    Quit,
+}
+
+// Local times like "08:00", an end before the start runs past midnight. Days are "mon" to "sun",
+// every day when empty. The passphrase never reaches the page.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AllowedHours {
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub days: Vec<String>,
+    #[serde(default)]
+    pub hide_to_tray: bool,
+    #[serde(default, skip_serializing)]
+    pub override_passphrase: Option<String>,
+}
+
+// Where links that ask for a new window go, login popups included.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NewWindowMode {
+    Browser,
+    ChildWindow,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct PlatformSpecific<T> {
     pub macos: T,
     pub linux: T,
@@ -40,24 +119,241 @@ where
     }
 }
 
-pub type UserAgent = PlatformSpecific<String>;
+// Rewrites a matching url, with $1, ${1}, ... standing for the capture groups.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Rewrite {
+    pub from_pattern: String,
+    pub to_template: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QuickLink {
+    pub title: String,
+    pub url: String,
+}
+
+// Steps run in order, each one an invoke command with its arguments, like
+// {"command": "set_zoom", "payload": {"factor": 1.0}}.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Macro {
+    pub shortcut: Option<String>,
+    pub steps: Vec<MacroStep>,
+    // Run the remaining steps after one fails, instead of stopping there.
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MacroStep {
+    pub command: String,
+    #[serde(default)]
+    pub payload: serde_json::Value,
+}
+
+// Keyboard shortcuts that resize the window by a fixed step, in logical pixels.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResizeStep {
+    pub width: f64,
+    pub height: f64,
+    pub shortcuts: ResizeShortcuts,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResizeShortcuts {
+    pub larger: Option<String>,
+    pub smaller: Option<String>,
+    pub wider: Option<String>,
+    pub taller: Option<String>,
+}
+
+// Commands run by swiping in from an edge of a touch screen.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EdgeSwipe {
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+}
+
+// Records the largest canvas on the page as a video, output_dir defaults to the download directory.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Recording {
+    #[serde(default)]
+    pub enabled: bool,
+    pub fps: Option<u32>,
+    pub output_dir: Option<String>,
+}
+
+// Windows taskbar tasks, args are command line flags like "--url https://example.com/inbox".
+// The icon is a .ico file relative to the app binary.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JumpListTask {
+    pub label: String,
+    pub args: String,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+}
+
+// search_url has a {query} placeholder, shortcut uses the menu accelerator format.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Omnibox {
+    #[serde(default)]
+    pub enabled: bool,
+    pub shortcut: String,
+    pub search_url: String,
+}
+
+// Protocols are listed by scheme, like "slack" or "spotify".
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExternalProtocols {
+    #[serde(default)]
+    pub ask: bool,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum PdfPageSize {
+    A4,
+    Letter,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureConsole {
+    Errors,
+    Warnings,
+    Off,
+}
+
+// Either one user agent for every platform, or one per platform.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum UserAgent {
+    Global(String),
+    PlatformSpecific(PlatformSpecific<String>),
+}
+
+impl UserAgent {
+    pub fn get(&self) -> &str {
+        match self {
+            UserAgent::Global(user_agent) => user_agent,
+            UserAgent::PlatformSpecific(user_agent) => user_agent.get(),
+        }
+    }
+}
+
 pub type FunctionON = PlatformSpecific<bool>;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct PakeConfig {
     pub windows: Vec<WindowConfig>,
     pub user_agent: UserAgent,
     pub menu: FunctionON,
+    // Drops the menu bar on every platform, for kiosk-style apps.
+    #[serde(default)]
+    pub hide_menu_bar: bool,
     pub system_tray: FunctionON,
+    pub pdf_page_size: Option<PdfPageSize>,
+    pub capture_console: Option<CaptureConsole>,
+    #[serde(default)]
+    pub allow_panels: bool,
+    #[serde(default)]
+    pub normalize_urls: bool,
+    pub quit_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub respect_before_unload: bool,
+    #[serde(default)]
+    pub enable_tray: bool,
+    #[serde(default)]
+    pub bring_to_front_on_click: bool,
+    #[serde(default)]
+    pub rewrites: Vec<Rewrite>,
+    #[serde(default)]
+    pub media_keys: bool,
+    // Holding Shift at launch skips the restore on macOS and Windows, Linux has no way to tell
+    // before a window has focus.
+    #[serde(default)]
+    pub restore_session: bool,
+    pub external_protocols: Option<ExternalProtocols>,
+    pub custom_css: Option<String>,
+    // CSS filter functions like "blur(20px) saturate(180%)" behind a transparent page.
+    pub backdrop_filter: Option<String>,
+    // Take the app icon, and the pinned title, from the page's web manifest.
+    #[serde(default)]
+    pub read_web_manifest: bool,
+    #[serde(default)]
+    pub pin_title: bool,
+    // Fields that the runtime pake.json and the command line can't change.
+    #[serde(default)]
+    pub locked_settings: Vec<String>,
+    // Can't be combined with new_window_mode, which already handles every new window link.
+    #[serde(default)]
+    pub open_blank_in_new_window: bool,
+    pub new_window_mode: Option<NewWindowMode>,
+    #[serde(default)]
+    pub hide_on_close: bool,
+    // Network simulation, only honoured in debug builds.
+    pub network_latency_ms: Option<u32>,
+    pub network_latency_jitter_ms: Option<u32>,
+    pub network_packet_loss_percent: Option<u8>,
+    #[serde(default)]
+    pub quick_links: Vec<QuickLink>,
+    pub omnibox: Option<Omnibox>,
+    #[serde(default)]
+    pub readonly_mode: bool,
+    // Regex patterns of urls that stay usable in readonly mode.
+    #[serde(default)]
+    pub readonly_allowlist: Vec<String>,
+    // When set, links leaving the app's domain open in the browser unless they match one of
+    // these regex patterns, like an OAuth callback. The app's domain includes its subdomains.
+    // Only clicked links are checked, redirects and pages setting window.location stay in the app.
+    pub internal_urls: Option<Vec<String>>,
+    // The same without exceptions, mailto: and tel: links go to the system's handlers too.
+    // Like there, only clicked links are checked.
+    #[serde(default)]
+    pub external_links_in_browser: bool,
+    pub focus_ring: Option<FocusRing>,
+    pub cmd_close_behavior: Option<CloseBehavior>,
+    // Show a spinner until the page has loaded.
+    #[serde(default)]
+    pub splash: bool,
+    pub allowed_hours: Option<AllowedHours>,
+    // Pages read them from window.PAKE_FLAGS, to turn on features for this build.
+    // Flags changed while the app runs arrive shortly after load, with a pake-flags-changed event.
+    #[serde(default)]
+    pub feature_flags: HashMap<String, bool>,
+    #[serde(default)]
+    pub macros: HashMap<String, Macro>,
+    // Shake the window when something the user asked for fails, like a macro or a wrong passphrase.
+    #[serde(default)]
+    pub shake_on_error: bool,
+    pub edge_swipe: Option<EdgeSwipe>,
+    #[serde(default)]
+    pub jump_list: Vec<JumpListTask>,
+    // CPU indices to pin the app to, the webview processes it starts inherit them on Linux.
+    #[serde(default)]
+    pub cpu_affinity: Vec<u32>,
+    pub recording: Option<Recording>,
+    pub resize_step: Option<ResizeStep>,
 }
 
 impl PakeConfig {
+    pub fn is_readonly_blocked(&self, url: &str) -> bool {
+        self.readonly_mode
+            && !self.readonly_allowlist.iter().any(|pattern| {
+                regex::Regex::new(pattern).map_or(false, |pattern| pattern.is_match(url))
+            })
+    }
+
     pub fn show_menu(&self) -> bool {
-        self.menu.copied()
+        !self.hide_menu_bar && self.menu.copied()
     }
 
     #[cfg(not(target_os = "macos"))]
     pub fn show_system_tray(&self) -> bool {
-        self.system_tray.copied()
+        self.enable_tray || self.system_tray.copied()
     }
 }
@@ -0,0 +1,155 @@
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+
+// The downloads panel only lists the most recent ones.
+const DOWNLOAD_LIMIT: usize = 20;
+// Weight of the newest speed sample, lower values give a steadier speed and ETA.
+const SPEED_SMOOTHING: f64 = 0.3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadStatus {
+    Active,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+// Sent to the page with every change, total is 0 when the size is unknown.
+#[derive(Clone, Serialize)]
+pub struct DownloadEntry {
+    id: usize,
+    filename: String,
+    path: String,
+    received: u64,
+    total: u64,
+    // Bytes per second, and the seconds left when the size is known.
+    speed: f64,
+    eta: Option<u64>,
+    status: DownloadStatus,
+    #[serde(skip)]
+    last_sample: Option<(Instant, u64)>,
+}
+
+static NEXT_DOWNLOAD_ID: AtomicUsize = AtomicUsize::new(1);
+static DOWNLOADS: Mutex<Vec<DownloadEntry>> = Mutex::new(Vec::new());
+
+pub fn add_download(app: &AppHandle, path: &str, total: u64) -> usize {
+    let id = NEXT_DOWNLOAD_ID.fetch_add(1, Ordering::SeqCst);
+    let filename = Path::new(path)
+        .file_name()
+        .map(|filename| filename.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    {
+        let mut downloads = DOWNLOADS.lock().unwrap();
+        downloads.push(DownloadEntry {
+            id,
+            filename,
+            path: path.to_string(),
+            received: 0,
+            total,
+            speed: 0.0,
+            eta: None,
+            status: DownloadStatus::Active,
+            last_sample: None,
+        });
+        // The oldest finished downloads make room first, running ones always stay listed.
+        while downloads.len() > DOWNLOAD_LIMIT {
+            match downloads
+                .iter()
+                .position(|download| download.status != DownloadStatus::Active)
+            {
+                Some(index) => {
+                    downloads.remove(index);
+                }
+                None => break,
+            }
+        }
+    }
+    emit_downloads(app);
+    id
+}
+
+pub fn update_download(app: &AppHandle, id: usize, received: u64, total: u64) {
+    update(app, id, |download| {
+        let now = Instant::now();
+        if let Some((sampled_at, sampled_bytes)) = download.last_sample {
+            let seconds = now.duration_since(sampled_at).as_secs_f64();
+            if seconds > 0.0 {
+                let sample = received.saturating_sub(sampled_bytes) as f64 / seconds;
+                download.speed = smooth_speed(download.speed, sample);
+            }
+        }
+        download.last_sample = Some((now, received));
+        download.received = received;
+        download.total = total;
+        download.eta = get_eta(received, total, download.speed);
+    });
+}
+
+// A cancelled download keeps its status when its transfer gives up afterwards.
+pub fn finish_download(app: &AppHandle, id: usize, status: DownloadStatus) {
+    update(app, id, |download| {
+        if download.status == DownloadStatus::Active {
+            download.status = status;
+        }
+        download.speed = 0.0;
+        download.eta = None;
+    });
+}
+
+// Without an id every running download is cancelled, they stop at their next chunk.
+pub fn cancel_download(app: &AppHandle, id: Option<usize>) {
+    for download in DOWNLOADS.lock().unwrap().iter_mut() {
+        if download.status == DownloadStatus::Active && id.map_or(true, |id| id == download.id) {
+            download.status = DownloadStatus::Cancelled;
+        }
+    }
+    emit_downloads(app);
+}
+
+pub fn is_cancelled(id: usize) -> bool {
+    DOWNLOADS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|download| download.id == id && download.status == DownloadStatus::Cancelled)
+}
+
+pub fn get_downloads() -> Vec<DownloadEntry> {
+    DOWNLOADS.lock().unwrap().clone()
+}
+
+fn smooth_speed(previous: f64, sample: f64) -> f64 {
+    if previous == 0.0 {
+        sample
+    } else {
+        previous + SPEED_SMOOTHING * (sample - previous)
+    }
+}
+
+fn get_eta(received: u64, total: u64, speed: f64) -> Option<u64> {
+    if total == 0 || speed <= 0.0 {
+        return None;
+    }
+    Some((total.saturating_sub(received) as f64 / speed).ceil() as u64)
+}
+
+fn update(app: &AppHandle, id: usize, change: impl FnOnce(&mut DownloadEntry)) {
+    {
+        let mut downloads = DOWNLOADS.lock().unwrap();
+        match downloads.iter_mut().find(|download| download.id == id) {
+            Some(download) => change(download),
+            None => return,
+        }
+    }
+    emit_downloads(app);
+}
+
+fn emit_downloads(app: &AppHandle) {
+    let _res = app.emit_all("downloads_changed", get_downloads());
+}
@@ -0,0 +1,106 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, Url};
+
+const LOCK_FILENAME: &str = ".pake-instance";
+const ACK: &str = "pake";
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+// How long a launch waits for the running instance to accept its token.
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+// A client that connects and never writes would otherwise hold up every later launch.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct InstanceLock {
+    listener: TcpListener,
+    token: String,
+}
+
+// Hand over to a running instance and exit, otherwise take the lock.
+// The lock file holds the port and a token the running instance listens with.
+pub fn acquire_instance_lock(data_dir: &Path) -> InstanceLock {
+    let lock_path = data_dir.join(LOCK_FILENAME);
+
+    if let Some(mut stream) = connect_to_running_instance(&lock_path) {
+        let url = get_url_arg().unwrap_or_default();
+        let _res = writeln!(stream, "{}", url);
+        process::exit(0);
+    }
+
+    // Nothing answered, so the lock file is missing or left behind by a crash. The port may belong
+    // to another process by now, so the lock file is replaced.
+    let _res = fs::remove_file(&lock_path);
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind the instance socket");
+    let port = listener.local_addr().unwrap().port();
+    let token = format!(
+        "{:x}{:x}",
+        process::id(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or(0)
+    );
+    if let Err(e) = fs::write(&lock_path, format!("{} {}", port, token)) {
+        eprintln!("Failed to write {}: {}", lock_path.display(), e);
+    }
+    InstanceLock { listener, token }
+}
+
+fn connect_to_running_instance(lock_path: &Path) -> Option<TcpStream> {
+    let content = fs::read_to_string(lock_path).ok()?;
+    let (port, token) = content.trim().split_once(' ')?;
+    let address = SocketAddr::from(([127, 0, 0, 1], port.parse().ok()?));
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).ok()?;
+    writeln!(stream, "{}", token).ok()?;
+
+    // Only the running instance knows the token and acks it.
+    stream.set_read_timeout(Some(ACK_TIMEOUT)).ok()?;
+    let mut ack = String::new();
+    BufReader::new(&stream).read_line(&mut ack).ok()?;
+    if ack.trim_end() != ACK {
+        return None;
+    }
+    Some(stream)
+}
+
+// An invalid url is dropped, the running instance then only comes to the front.
+fn get_url_arg() -> Option<String> {
+    let mut args = env::args().skip_while(|arg| arg != "--url");
+    args.next()?;
+    args.next().filter(|url| Url::parse(url).is_ok())
+}
+
+// Later launches focus the main window, and load the url they were given.
+pub fn listen_for_instances(lock: InstanceLock, app: AppHandle) {
+    std::thread::spawn(move || {
+        for stream in lock.listener.incoming().flatten() {
+            if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+                continue;
+            }
+            let mut lines = BufReader::new(&stream).lines();
+            if lines.next().and_then(Result::ok).as_deref() != Some(lock.token.as_str()) {
+                continue;
+            }
+            if writeln!(&stream, "{}", ACK).is_err() {
+                continue;
+            }
+            let url = lines.next().and_then(Result::ok).unwrap_or_default();
+
+            if let Some(window) = app.get_window("pake") {
+                let _res = window.unminimize();
+                let _res = window.show();
+                let _res = window.set_focus();
+                if !url.is_empty() {
+                    let _res = window.eval(&format!(
+                        "window.location.href = {};",
+                        serde_json::to_string(&url).unwrap()
+                    ));
+                }
+            }
+        }
+    });
+}
@@ -1,6 +1,31 @@
-use crate::util::{check_file_or_append, get_download_message, show_toast};
-use download_rs::sync_download::Download;
-use tauri::{api, command, AppHandle, Manager, Window};
+use crate::app::allowed_hours;
+use crate::app::config::{PakeConfig, Recording};
+use crate::app::downloads::{self, DownloadStatus};
+use crate::app::state::{AppearanceState, HistoryEntry, PanelState, StateStore};
+use crate::app::window::{
+    close_main_window, fit_window_to_content, get_main_url, handle_close_shortcut,
+    is_main_always_on_top, is_popup_window, mark_quit_ready, open_link_window, open_panel_window,
+    open_popup_window, request_close_main_window, resume_auto_fit, set_main_always_on_top,
+    shake_window, step_window_size, toggle_window_always_on_top, MANIFEST_ICON_FILENAME,
+    PANEL_LABEL_PREFIX,
+};
+use crate::util::{
+    check_file_or_append, check_scale, devtools_requested, get_data_dir, get_download_message,
+    rank_switcher_items, read_feature_flags, sanitize_filename, show_toast, store_feature_flag,
+    Coalescer, SwitcherItem,
+};
+use base64::Engine;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+#[cfg(not(target_os = "linux"))]
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::api::dialog::blocking::FileDialogBuilder;
+use tauri::{api, command, AppHandle, ClipboardManager, Icon, Manager, Window};
 
 #[derive(serde::Deserialize)]
 pub struct DownloadFileParams {
@@ -8,20 +33,1081 @@ pub struct DownloadFileParams {
     filename: String,
 }
 
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct ConsoleErrorParams {
+    level: String,
+    message: String,
+    source: Option<String>,
+    line: Option<u32>,
+    stack: Option<String>,
+    // Filled in by the app, in seconds since the Unix epoch.
+    #[serde(default)]
+    time: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PanelParams {
+    url: String,
+    width: Option<f64>,
+    height: Option<f64>,
+    always_on_top: Option<bool>,
+    frameless: Option<bool>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PopupParams {
+    url: String,
+    width: Option<f64>,
+    height: Option<f64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct WebManifestParams {
+    name: Option<String>,
+    icon_url: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct AppInfo {
+    name: String,
+    version: String,
+    readonly_mode: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NotifyParams {
+    title: String,
+    body: String,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct RewriteLogEntry {
+    from: String,
+    to: String,
+}
+
+const DOWNLOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+struct BlobDownload {
+    id: usize,
+    file: File,
+    path: String,
+    received: u64,
+    size: u64,
+}
+
+static BLOB_DOWNLOADS: Mutex<Vec<BlobDownload>> = Mutex::new(Vec::new());
+// Pages may only open what this session downloaded, not any path they pass in.
+static DOWNLOADED_FILES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Some pages rewrite their title many times a second while switching routes.
+const TITLE_INTERVAL: Duration = Duration::from_millis(250);
+static TITLE: Mutex<Coalescer<String>> = Mutex::new(Coalescer::new(TITLE_INTERVAL));
+static TITLE_FLUSH_SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+const HISTORY_LIMIT: usize = 100;
+const READONLY_MESSAGE: &str = "This app is in read-only mode";
+
+static UI_DEGRADED_LOGGED: AtomicBool = AtomicBool::new(false);
+
+// Page errors logged per period, so a noisy page can't flood the log.
+const CONSOLE_ERROR_LIMIT: u32 = 20;
+const CONSOLE_ERROR_PERIOD: Duration = Duration::from_secs(10);
+static CONSOLE_ERROR_COUNT: Mutex<Option<(Instant, u32)>> = Mutex::new(None);
+// Logged errors also go to a file in the data directory, so users can send it along with a report.
+// Past the size limit it starts over, and the previous one is kept as console.log.1.
+const CONSOLE_LOG_FILENAME: &str = "console.log";
+const CONSOLE_LOG_MAX_SIZE: u64 = 1024 * 1024;
+const RECENT_CONSOLE_ERROR_LIMIT: usize = 50;
+static RECENT_CONSOLE_ERRORS: Mutex<Vec<ConsoleErrorParams>> = Mutex::new(Vec::new());
+
+const REWRITE_LOG_LIMIT: usize = 50;
+static REWRITE_LOG: Mutex<Vec<RewriteLogEntry>> = Mutex::new(Vec::new());
+
 #[command]
-pub async fn download_file(app: AppHandle, params: DownloadFileParams) -> Result<(), String> {
-    let window: Window = app.get_window("pake").unwrap();
-    let output_path = api::path::download_dir().unwrap().join(params.filename);
-    let file_path = check_file_or_append(output_path.to_str().unwrap());
-    let download = Download::new(&params.url, Some(&file_path), None);
-    match download.download() {
+pub async fn download_file(
+    app: AppHandle,
+    window: Window,
+    params: DownloadFileParams,
+) -> Result<(), String> {
+    if window
+        .state::<PakeConfig>()
+        .is_readonly_blocked(window.url().as_str())
+    {
+        show_toast(&window, READONLY_MESSAGE);
+        return Err("readonly".to_string());
+    }
+    let file_path = match resolve_download_path(&window, &params.filename).await? {
+        Some(file_path) => file_path,
+        None => return Ok(()),
+    };
+    let id = downloads::add_download(&app, &file_path, 0);
+    let result = download_to_file(&window, id, &params.url, &file_path).await;
+    let _res = window.eval("pakeDownloadProgress(0, 0, true);");
+    match result {
         Ok(_) => {
-            show_toast(&window, &get_download_message());
+            downloads::finish_download(&app, id, DownloadStatus::Done);
+            show_download_complete(&window, file_path);
             Ok(())
         }
         Err(e) => {
-            show_toast(&window, &e.to_string());
-            Err(e.to_string())
+            downloads::finish_download(&app, id, DownloadStatus::Failed);
+            show_toast(&window, &e);
+            Err(e)
+        }
+    }
+}
+
+// None when the user dismissed the save dialog.
+async fn resolve_download_path(window: &Window, filename: &str) -> Result<Option<String>, String> {
+    let filename = sanitize_filename(filename);
+    if asks_for_download_path(window) {
+        return ask_download_path(window, filename).await;
+    }
+    let output_path = get_download_dir(window).join(filename);
+    Ok(Some(check_file_or_append(output_path.to_str().unwrap())))
+}
+
+fn show_download_complete(window: &Window, file_path: String) {
+    let _res = window.eval(&format!(
+        "pakeDownloadToast({}, {});",
+        serde_json::to_string(&get_download_message()).unwrap(),
+        serde_json::to_string(&file_path).unwrap()
+    ));
+    DOWNLOADED_FILES.lock().unwrap().push(file_path);
+}
+
+// Files made by the page, from blob: and data: urls, arrive in base64 chunks so large
+// ones don't hold up the page. None of the file is kept when the page gives up.
+#[command]
+pub async fn start_blob_download(
+    window: Window,
+    filename: String,
+    size: u64,
+) -> Result<Option<usize>, String> {
+    if window
+        .state::<PakeConfig>()
+        .is_readonly_blocked(window.url().as_str())
+    {
+        show_toast(&window, READONLY_MESSAGE);
+        return Err("readonly".to_string());
+    }
+    let path = match resolve_download_path(&window, &filename).await? {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    add_blob_download(&window.app_handle(), path, size).map(Some)
+}
+
+// Blob downloads share their id with the downloads panel.
+fn add_blob_download(app: &AppHandle, path: String, size: u64) -> Result<usize, String> {
+    let file = File::create(&path).map_err(|e| e.to_string())?;
+    let id = downloads::add_download(app, &path, size);
+    BLOB_DOWNLOADS.lock().unwrap().push(BlobDownload {
+        id,
+        file,
+        path,
+        received: 0,
+        size,
+    });
+    Ok(id)
+}
+
+#[command]
+pub async fn write_blob_chunk(window: Window, id: usize, data: String) -> Result<(), String> {
+    if downloads::is_cancelled(id) {
+        return Err("Download cancelled".to_string());
+    }
+    let chunk = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| e.to_string())?;
+    let mut downloads = BLOB_DOWNLOADS.lock().unwrap();
+    let download = downloads
+        .iter_mut()
+        .find(|download| download.id == id)
+        .ok_or("Unknown download")?;
+    download.file.write_all(&chunk).map_err(|e| e.to_string())?;
+    download.received += chunk.len() as u64;
+    show_download_progress(&window, download.received, download.size);
+    downloads::update_download(&window.app_handle(), id, download.received, download.size);
+    Ok(())
+}
+
+#[command]
+pub async fn finish_blob_download(window: Window, id: usize, complete: bool) -> Result<(), String> {
+    let download = {
+        let mut downloads = BLOB_DOWNLOADS.lock().unwrap();
+        let index = downloads
+            .iter()
+            .position(|download| download.id == id)
+            .ok_or("Unknown download")?;
+        downloads.remove(index)
+    };
+    let _res = window.eval("pakeDownloadProgress(0, 0, true);");
+    drop(download.file);
+    if complete {
+        downloads::finish_download(&window.app_handle(), id, DownloadStatus::Done);
+        show_download_complete(&window, download.path);
+    } else {
+        downloads::finish_download(&window.app_handle(), id, DownloadStatus::Failed);
+        let _res = std::fs::remove_file(&download.path);
+    }
+    Ok(())
+}
+
+fn asks_for_download_path(window: &Window) -> bool {
+    window
+        .state::<PakeConfig>()
+        .windows
+        .first()
+        .and_then(|window_config| window_config.download_path.as_deref())
+        == Some("ask")
+}
+
+// None when the save dialog is dismissed, which cancels the download.
+async fn ask_download_path(window: &Window, filename: String) -> Result<Option<String>, String> {
+    let dialog_window = window.clone();
+    let chosen_path = tauri::async_runtime::spawn_blocking(move || {
+        FileDialogBuilder::new()
+            .set_parent(&dialog_window)
+            .set_directory(get_system_download_dir())
+            .set_file_name(&filename)
+            .save_file()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(chosen_path.map(|path| path.to_string_lossy().into_owned()))
+}
+
+// Minimal Linux installs may have no download directory, fall back to the home directory.
+fn get_system_download_dir() -> PathBuf {
+    api::path::download_dir()
+        .or_else(api::path::home_dir)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+// A configured download_path is used when it exists or can be created.
+fn get_download_dir(window: &Window) -> PathBuf {
+    let download_path = window
+        .state::<PakeConfig>()
+        .windows
+        .first()
+        .and_then(|window_config| window_config.download_path.clone());
+    get_output_dir(window, download_path.as_deref())
+}
+
+// A configured directory that can't be created falls back to the system download directory.
+fn get_output_dir(window: &Window, path: Option<&str>) -> PathBuf {
+    if let Some(path) = path {
+        let dir = match path.strip_prefix("~/") {
+            Some(relative_path) => api::path::home_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join(relative_path),
+            None => PathBuf::from(path),
+        };
+        match std::fs::create_dir_all(&dir) {
+            Ok(_) => return dir,
+            Err(e) => show_toast(
+                window,
+                &format!(
+                    "Can't use {}, saving to the download directory: {}",
+                    dir.display(),
+                    e
+                ),
+            ),
+        }
+    }
+    get_system_download_dir()
+}
+
+// Without a Content-Length the total is reported as 0, and the page shows an indeterminate bar.
+async fn download_to_file(
+    window: &Window,
+    id: usize,
+    url: &str,
+    file_path: &str,
+) -> Result<(), String> {
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let total = response.content_length().unwrap_or(0);
+    let mut file = File::create(file_path).map_err(|e| e.to_string())?;
+    let mut received = 0;
+    let mut progress = Coalescer::new(DOWNLOAD_PROGRESS_INTERVAL);
+
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if downloads::is_cancelled(id) {
+            drop(file);
+            let _res = std::fs::remove_file(file_path);
+            return Err("Download cancelled".to_string());
+        }
+        file.write_all(&chunk).map_err(|e| e.to_string())?;
+        received += chunk.len() as u64;
+        if let Some(received) = progress.push(received, Instant::now()) {
+            show_download_progress(window, received, total);
+            downloads::update_download(&window.app_handle(), id, received, total);
         }
     }
+    if let Some(received) = progress.flush(Instant::now()) {
+        show_download_progress(window, received, total);
+        downloads::update_download(&window.app_handle(), id, received, total);
+    }
+    Ok(())
+}
+
+fn show_download_progress(window: &Window, received: u64, total: u64) {
+    let _res = window.eval(&format!(
+        "pakeDownloadProgress({}, {}, false);",
+        received, total
+    ));
+}
+
+fn check_downloaded_file(path: &str) -> Result<(), String> {
+    if DOWNLOADED_FILES
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|file| file == path)
+    {
+        Ok(())
+    } else {
+        Err(format!("{} was not downloaded by this app", path))
+    }
+}
+
+#[command]
+pub fn open_downloaded_file(path: String) -> Result<(), String> {
+    check_downloaded_file(&path)?;
+    open::that(&path).map_err(|e| e.to_string())
+}
+
+// Selects the file in Finder and Explorer, Linux file managers only get to open the folder.
+#[command]
+pub fn reveal_in_folder(path: String) -> Result<(), String> {
+    check_downloaded_file(&path)?;
+
+    #[cfg(target_os = "macos")]
+    let result = Command::new("open")
+        .args(["-R", path.as_str()])
+        .spawn()
+        .map(|_| ());
+    #[cfg(target_os = "windows")]
+    let result = Command::new("explorer")
+        .arg(format!("/select,{}", path))
+        .spawn()
+        .map(|_| ());
+    #[cfg(target_os = "linux")]
+    let result = open::that(
+        PathBuf::from(&path)
+            .parent()
+            .unwrap_or_else(|| "/".as_ref()),
+    );
+
+    result.map_err(|e| e.to_string())
+}
+
+// Without an id every running download is cancelled.
+#[command]
+pub fn cancel_download(app: AppHandle, id: Option<usize>) {
+    downloads::cancel_download(&app, id);
+}
+
+#[command]
+pub fn get_downloads() -> Vec<downloads::DownloadEntry> {
+    downloads::get_downloads()
+}
+
+#[command]
+pub fn toggle_downloads_panel(window: Window) -> Result<(), String> {
+    window
+        .eval("pakeToggleDownloads();")
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn set_scale(window: Window, scale: f64) -> Result<(), String> {
+    let scale = check_scale(scale)?;
+    window
+        .eval(&format!("setForceScale({});", scale))
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn set_zoom(window: Window, factor: f64) -> Result<(), String> {
+    if !factor.is_finite() {
+        return Err(format!("Invalid zoom factor {}", factor));
+    }
+    // setZoom persists the zoom in localStorage, so it survives restarts.
+    let zoom = (factor.clamp(0.25, 5.0) * 100.0).round();
+    window
+        .eval(&format!("setZoom('{}%');", zoom))
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_zoom(app: AppHandle) -> f64 {
+    app.state::<StateStore>().read(|state| state.zoom)
+}
+
+#[command]
+pub fn save_zoom(app: AppHandle, zoom: f64) {
+    app.state::<StateStore>().update(|state| state.zoom = zoom);
+}
+
+#[command]
+pub fn save_appearance(window: Window, mut params: AppearanceState) {
+    if window.label() != "pake" {
+        return;
+    }
+    params.url = get_main_url(&window.state::<PakeConfig>()).to_string();
+    window
+        .state::<StateStore>()
+        .update(|state| state.appearance = Some(params));
+}
+
+// Find in page for sites that take over Ctrl+F, the search itself lives in event.js.
+#[command]
+pub fn find(window: Window, query: String) -> Result<(), String> {
+    let query = serde_json::to_string(&query).unwrap();
+    window
+        .eval(&format!("pakeFind({});", query))
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn find_next(window: Window) -> Result<(), String> {
+    window.eval("pakeFindNext();").map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn find_clear(window: Window) -> Result<(), String> {
+    window.eval("pakeFindClear();").map_err(|e| e.to_string())
+}
+
+// For a page that got stuck, the zoom is saved by the app and comes back after either one.
+#[command]
+pub fn reload(window: Window) -> Result<(), String> {
+    window
+        .eval("window.location.reload();")
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn hard_reload(window: Window) -> Result<(), String> {
+    window.eval("pakeHardReload();").map_err(|e| e.to_string())
+}
+
+// For navigation buttons, only macOS has back and forward swipes.
+#[command]
+pub fn go_back(window: Window) -> Result<(), String> {
+    window
+        .eval("window.history.back();")
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn go_forward(window: Window) -> Result<(), String> {
+    window
+        .eval("window.history.forward();")
+        .map_err(|e| e.to_string())
+}
+
+const DEFAULT_RECORDING_FPS: u32 = 30;
+
+fn get_recording(window: &Window) -> Result<Recording, String> {
+    window
+        .state::<PakeConfig>()
+        .recording
+        .as_ref()
+        .filter(|recording| recording.enabled)
+        .cloned()
+        .ok_or_else(|| "Recording is not enabled".to_string())
+}
+
+// The recorder lives in event.js, the page sends the video back through save_recording.
+#[command]
+pub fn recording_start(window: Window) -> Result<(), String> {
+    let fps = get_recording(&window)?.fps.unwrap_or(DEFAULT_RECORDING_FPS);
+    window
+        .eval(&format!("pakeRecordingStart({});", fps))
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn recording_stop(window: Window, filename: String) -> Result<(), String> {
+    get_recording(&window)?;
+    window
+        .eval(&format!(
+            "pakeRecordingStop({});",
+            serde_json::to_string(&filename).unwrap()
+        ))
+        .map_err(|e| e.to_string())
+}
+
+// The video then arrives through write_blob_chunk and finish_blob_download, like a download.
+#[command]
+pub fn save_recording(window: Window, filename: String, size: u64) -> Result<usize, String> {
+    if window
+        .state::<PakeConfig>()
+        .is_readonly_blocked(window.url().as_str())
+    {
+        show_toast(&window, READONLY_MESSAGE);
+        return Err("readonly".to_string());
+    }
+    let recording = get_recording(&window)?;
+    let dir = match recording.output_dir.as_deref() {
+        Some(output_dir) => get_output_dir(&window, Some(output_dir)),
+        None => get_download_dir(&window),
+    };
+    let path = check_file_or_append(dir.join(sanitize_filename(&filename)).to_str().unwrap());
+    add_blob_download(&window.app_handle(), path, size)
+}
+
+#[command]
+pub fn print_page(window: Window) -> Result<(), String> {
+    window.print().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn console_error(app: AppHandle, mut params: ConsoleErrorParams) {
+    let mut count = CONSOLE_ERROR_COUNT.lock().unwrap();
+    let now = Instant::now();
+    match *count {
+        Some((start, logged)) if now.duration_since(start) < CONSOLE_ERROR_PERIOD => {
+            if logged >= CONSOLE_ERROR_LIMIT {
+                return;
+            }
+            *count = Some((start, logged + 1));
+        }
+        _ => *count = Some((now, 1)),
+    }
+
+    params.time = unix_now();
+    let mut entry = format!(
+        "[page {}] {} ({}:{})",
+        params.level,
+        params.message,
+        params.source.as_deref().unwrap_or("unknown source"),
+        params.line.unwrap_or(0)
+    );
+    if let Some(stack) = &params.stack {
+        entry = format!("{}\n{}", entry, stack);
+    }
+    eprintln!("{}", entry);
+    append_console_log(
+        &get_data_dir(&app.config()),
+        &format!("{} {}\n", params.time, entry),
+    );
+
+    let mut recent = RECENT_CONSOLE_ERRORS.lock().unwrap();
+    if recent.len() >= RECENT_CONSOLE_ERROR_LIMIT {
+        recent.remove(0);
+    }
+    recent.push(params);
+}
+
+fn append_console_log(data_dir: &Path, entry: &str) {
+    let path = data_dir.join(CONSOLE_LOG_FILENAME);
+    if std::fs::metadata(&path).map_or(false, |metadata| metadata.len() > CONSOLE_LOG_MAX_SIZE) {
+        let _res = std::fs::rename(&path, path.with_extension("log.1"));
+    }
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+        let _res = file.write_all(entry.as_bytes());
+    }
+}
+
+// The most recent page errors, oldest first.
+#[command]
+pub fn get_console_errors() -> Vec<ConsoleErrorParams> {
+    RECENT_CONSOLE_ERRORS.lock().unwrap().clone()
+}
+
+#[command]
+pub async fn open_panel(app: AppHandle, params: PanelParams) -> Result<String, String> {
+    let panel = PanelState {
+        url: params.url,
+        width: params.width.unwrap_or(400.0),
+        height: params.height.unwrap_or(600.0),
+        x: None,
+        y: None,
+        always_on_top: params.always_on_top.unwrap_or(true),
+        frameless: params.frameless.unwrap_or(false),
+    };
+    open_panel_window(&app, &panel)
+}
+
+#[command]
+pub fn close_panel(app: AppHandle, id: String) -> Result<(), String> {
+    if !id.starts_with(PANEL_LABEL_PREFIX) {
+        return Err(format!("{} is not a panel", id));
+    }
+    match app.get_window(&id) {
+        Some(window) => window.close().map_err(|e| e.to_string()),
+        None => Err(format!("Panel {} does not exist", id)),
+    }
+}
+
+#[command]
+pub fn ui_degraded(window: Window, reason: String) {
+    // Only the first report of a session is worth logging.
+    if !UI_DEGRADED_LOGGED.swap(true, Ordering::SeqCst) {
+        eprintln!(
+            "Injected styles are blocked on {} ({}), using fallback UI",
+            window.url(),
+            reason
+        );
+    }
+}
+
+#[command]
+pub fn quit_ready() {
+    mark_quit_ready();
+}
+
+#[command]
+pub fn before_unload_result(window: Window, blocked: bool) {
+    if !blocked {
+        close_main_window(&window);
+        return;
+    }
+
+    let parent_window = window.clone();
+    api::dialog::ask(
+        Some(&parent_window),
+        "Leave site?",
+        "Changes you made may not be saved.",
+        move |leave| {
+            if leave {
+                close_main_window(&window);
+            }
+        },
+    );
+}
+
+#[command]
+pub fn record_rewrite(params: RewriteLogEntry) {
+    let mut log = REWRITE_LOG.lock().unwrap();
+    if log.len() >= REWRITE_LOG_LIMIT {
+        log.remove(0);
+    }
+    log.push(params);
+}
+
+// Only for debugging, so it is limited to debug builds and PAKE_DEVTOOLS.
+#[command]
+pub fn get_rewrite_log() -> Result<Vec<RewriteLogEntry>, String> {
+    if cfg!(debug_assertions) || devtools_requested() {
+        Ok(REWRITE_LOG.lock().unwrap().clone())
+    } else {
+        Err("The rewrite log is only available in debug builds".to_string())
+    }
+}
+
+#[command]
+pub fn set_a11y_mode(window: Window, enabled: bool) -> Result<(), String> {
+    window
+        .eval(&format!("setA11yMode({});", enabled))
+        .map_err(|e| e.to_string())
+}
+
+// Links to other apps are opened only when allowed, and the full url is shown before asking.
+#[command]
+pub fn open_external_protocol(window: Window, url: String) -> Result<(), String> {
+    let config = window.state::<PakeConfig>();
+    let protocols = config
+        .external_protocols
+        .as_ref()
+        .ok_or("External protocols are not enabled")?;
+    let scheme = url.split(':').next().unwrap_or_default().to_lowercase();
+    let is_listed = |list: &[String]| {
+        list.iter()
+            .any(|protocol| protocol.to_lowercase() == scheme)
+    };
+
+    if is_listed(&protocols.deny) {
+        eprintln!("Blocked opening {}", url);
+        return Err(format!("The {} protocol is blocked", scheme));
+    }
+    if is_listed(&protocols.allow) {
+        return open::that(&url).map_err(|e| e.to_string());
+    }
+    if !protocols.ask {
+        eprintln!("Ignored opening {}", url);
+        return Err(format!("The {} protocol is not allowed", scheme));
+    }
+
+    api::dialog::ask(
+        Some(&window),
+        "Open external application?",
+        format!(
+            "This app wants to open the following link in an external application:\n\n{}\n\nAllow?",
+            url
+        ),
+        move |allow| {
+            if allow {
+                if let Err(e) = open::that(&url) {
+                    eprintln!("Failed to open {}: {}", url, e);
+                }
+            }
+        },
+    );
+    Ok(())
+}
+
+// The webview has no storage prompt, the grant is remembered for the origin of the window.
+// wry can't mark an origin persistent, so the answer only tells the page what the user chose.
+#[command]
+pub async fn request_persistent_storage(window: Window) -> Result<bool, String> {
+    let origin = window.url().origin().ascii_serialization();
+    if is_persistent_origin(&window, &origin) {
+        return Ok(true);
+    }
+
+    let message = format!(
+        "{} wants to keep its data on this device, so it isn't cleared when space runs low.\n\nAllow?",
+        origin
+    );
+    let dialog_window = window.clone();
+    let granted = tauri::async_runtime::spawn_blocking(move || {
+        api::dialog::blocking::ask(Some(&dialog_window), "Keep data on this device?", message)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if granted {
+        window.state::<StateStore>().update(|state| {
+            if !state.persistent_origins.contains(&origin) {
+                state.persistent_origins.push(origin);
+            }
+        });
+    }
+    Ok(granted)
+}
+
+fn is_persistent_origin(window: &Window, origin: &str) -> bool {
+    window
+        .state::<StateStore>()
+        .read(|state| state.persistent_origins.iter().any(|saved| saved == origin))
+}
+
+#[command]
+pub fn is_storage_persisted(window: Window) -> bool {
+    is_persistent_origin(&window, &window.url().origin().ascii_serialization())
+}
+
+#[command]
+pub fn reset_permissions(window: Window) {
+    window.state::<StateStore>().update(|state| {
+        state.persistent_origins.clear();
+        state.notification_origins.clear();
+    });
+}
+
+// Answers like Notification.permission does.
+#[command]
+pub fn get_notification_permission(window: Window) -> &'static str {
+    match is_notification_origin(&window, &window.url().origin().ascii_serialization()) {
+        Some(true) => "granted",
+        Some(false) => "denied",
+        None => "default",
+    }
+}
+
+#[command]
+pub async fn request_notification_permission(window: Window) -> Result<&'static str, String> {
+    let origin = window.url().origin().ascii_serialization();
+    if let Some(granted) = is_notification_origin(&window, &origin) {
+        return Ok(if granted { "granted" } else { "denied" });
+    }
+
+    let message = format!("{} wants to show notifications.\n\nAllow?", origin);
+    let dialog_window = window.clone();
+    let granted = tauri::async_runtime::spawn_blocking(move || {
+        api::dialog::blocking::ask(Some(&dialog_window), "Show notifications?", message)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    window.state::<StateStore>().update(|state| {
+        state.notification_origins.insert(origin, granted);
+    });
+    Ok(if granted { "granted" } else { "denied" })
+}
+
+fn is_notification_origin(window: &Window, origin: &str) -> Option<bool> {
+    window
+        .state::<StateStore>()
+        .read(|state| state.notification_origins.get(origin).copied())
+}
+
+// Sizes come from the page in CSS pixels, at the given page zoom.
+#[command]
+pub fn fit_window(window: Window, width: f64, height: f64, zoom: f64) -> Result<(), String> {
+    let auto_fit = window
+        .state::<PakeConfig>()
+        .windows
+        .first()
+        .map_or(false, |window_config| window_config.auto_fit);
+    if window.label() != "pake" || !auto_fit {
+        return Err("Auto-fit is not enabled".to_string());
+    }
+    if ![width, height, zoom].iter().all(|value| value.is_finite()) {
+        return Err("Invalid content size".to_string());
+    }
+    fit_window_to_content(&window, width, height, zoom)
+}
+
+#[command]
+pub fn refit_window(window: Window) -> Result<(), String> {
+    resume_auto_fit(&window)
+}
+
+// The icon is kept in the data directory, so the next launch shows it from the start.
+#[command]
+pub async fn apply_web_manifest(window: Window, params: WebManifestParams) -> Result<(), String> {
+    let (read_web_manifest, pin_title) = {
+        let config = window.state::<PakeConfig>();
+        (config.read_web_manifest, config.pin_title)
+    };
+    if window.label() != "pake" || !read_web_manifest {
+        return Err("Reading the web manifest is not enabled".to_string());
+    }
+
+    // Otherwise the title follows the page title.
+    if let Some(name) = params.name.filter(|_| pin_title) {
+        window.set_title(&name).map_err(|e| e.to_string())?;
+    }
+
+    if let Some(icon_url) = params.icon_url {
+        let icon = reqwest::get(&icon_url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_vec();
+        window
+            .set_icon(Icon::Raw(icon.clone()))
+            .map_err(|e| e.to_string())?;
+        let data_dir = get_data_dir(&window.config());
+        let _res = std::fs::write(data_dir.join(MANIFEST_ICON_FILENAME), icon);
+    }
+    Ok(())
+}
+
+// Directions are the names of the resize_step shortcuts.
+#[command]
+pub fn resize_step(window: Window, direction: String) -> Result<(), String> {
+    let (width_steps, height_steps) = match direction.as_str() {
+        "larger" => (1.0, 1.0),
+        "smaller" => (-1.0, -1.0),
+        "wider" => (1.0, 0.0),
+        "taller" => (0.0, 1.0),
+        _ => return Err(format!("Unknown resize direction {}", direction)),
+    };
+    step_window_size(&window, width_steps, height_steps)
+}
+
+#[command]
+pub fn minimize(window: Window) -> Result<(), String> {
+    window.minimize().map_err(|e| e.to_string())
+}
+
+// Behaves like the titlebar close button, including the tray and beforeunload handling.
+#[command]
+pub fn close_window(window: Window) -> Result<(), String> {
+    if window.label() == "pake" {
+        request_close_main_window(&window);
+        Ok(())
+    } else {
+        window.close().map_err(|e| e.to_string())
+    }
+}
+
+#[command]
+pub fn close_shortcut(window: Window) {
+    handle_close_shortcut(&window);
+}
+
+// Lifts the allowed hours until the app quits.
+#[command]
+pub fn override_allowed_hours(window: Window, passphrase: String) -> Result<(), String> {
+    allowed_hours::override_allowed_hours(&window.state::<PakeConfig>(), &passphrase)
+}
+
+#[command]
+pub fn get_feature_flags(window: Window) -> HashMap<String, bool> {
+    read_feature_flags(&window.state::<PakeConfig>())
+}
+
+// Every open page sees the change, and gets a pake-flags-changed event.
+#[command]
+pub fn set_feature_flag(app: AppHandle, key: String, enabled: bool) -> Result<(), String> {
+    store_feature_flag(&app.state::<PakeConfig>(), &key, enabled)?;
+    let script = format!(
+        "window.PAKE_FLAGS[{key}] = {enabled};
+        window.dispatchEvent(new CustomEvent('pake-flags-changed', {{ detail: window.PAKE_FLAGS }}));",
+        key = serde_json::to_string(&key).unwrap(),
+        enabled = enabled
+    );
+    for (label, window) in app.windows() {
+        if !is_popup_window(&label) {
+            let _res = window.eval(&script);
+        }
+    }
+    Ok(())
+}
+
+#[command]
+pub fn hide_window(window: Window) -> Result<(), String> {
+    window.hide().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn toggle_always_on_top(window: Window) -> Result<bool, String> {
+    if window.label() == "pake" {
+        let always_on_top = !is_main_always_on_top();
+        set_main_always_on_top(&window, always_on_top)?;
+        return Ok(always_on_top);
+    }
+
+    toggle_window_always_on_top(&window)
+}
+
+#[command]
+pub fn shake(window: Window) {
+    shake_window(&window);
+}
+
+#[command]
+pub fn set_title(window: Window, title: String) {
+    if window.label() != "pake" || window.state::<PakeConfig>().pin_title {
+        return;
+    }
+
+    match TITLE.lock().unwrap().push(title, Instant::now()) {
+        Some(title) => {
+            let _res = window.set_title(&title);
+        }
+        None if !TITLE_FLUSH_SCHEDULED.swap(true, Ordering::SeqCst) => {
+            std::thread::spawn(move || {
+                std::thread::sleep(TITLE_INTERVAL);
+                TITLE_FLUSH_SCHEDULED.store(false, Ordering::SeqCst);
+                if let Some(title) = TITLE.lock().unwrap().flush(Instant::now()) {
+                    let _res = window.set_title(&title);
+                }
+            });
+        }
+        None => {}
+    }
+}
+
+// Windows must be created from an async command, a sync one deadlocks on Windows.
+#[command]
+pub async fn open_window(app: AppHandle, url: String) -> Result<String, String> {
+    open_link_window(&app, &url)
+}
+
+#[command]
+pub async fn open_popup(app: AppHandle, params: PopupParams) -> Result<String, String> {
+    open_popup_window(
+        &app,
+        &params.url,
+        params.width.unwrap_or(500.0),
+        params.height.unwrap_or(650.0),
+    )
+}
+
+// Pages can bind this to a shortcut with invoke('copy_current_url').
+#[command]
+pub fn copy_current_url(window: Window) -> Result<String, String> {
+    let url = window.url().to_string();
+    if window.state::<PakeConfig>().is_readonly_blocked(&url) {
+        show_toast(&window, READONLY_MESSAGE);
+        return Err("readonly".to_string());
+    }
+    window
+        .app_handle()
+        .clipboard_manager()
+        .write_text(url.clone())
+        .map_err(|e| e.to_string())?;
+    show_toast(&window, "URL copied");
+    Ok(url)
+}
+
+#[command]
+pub fn record_visit(window: Window, url: String, title: String) {
+    if window.label() != "pake" {
+        return;
+    }
+
+    let now = unix_now();
+    window.state::<StateStore>().update(|state| {
+        match state.history.iter_mut().find(|entry| entry.url == url) {
+            Some(entry) => {
+                entry.title = title;
+                entry.visits += 1;
+                entry.last_visit = now;
+            }
+            None => state.history.push(HistoryEntry {
+                url,
+                title,
+                visits: 1,
+                last_visit: now,
+            }),
+        }
+        state
+            .history
+            .sort_by(|a, b| b.last_visit.cmp(&a.last_visit));
+        state.history.truncate(HISTORY_LIMIT);
+    });
+}
+
+#[command]
+pub fn get_switcher_items(window: Window, query: String) -> Vec<SwitcherItem> {
+    let history = window
+        .state::<StateStore>()
+        .read(|state| state.history.clone());
+    rank_switcher_items(
+        &window.state::<PakeConfig>().quick_links,
+        &history,
+        &query,
+        unix_now(),
+    )
+}
+
+#[command]
+pub fn toggle_switcher(window: Window) -> Result<(), String> {
+    window.eval("toggleSwitcher();").map_err(|e| e.to_string())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[command]
+pub fn notify(window: Window, params: NotifyParams) -> Result<(), String> {
+    let origin = window.url().origin().ascii_serialization();
+    if is_notification_origin(&window, &origin) != Some(true) {
+        return Err("Notifications are not allowed".to_string());
+    }
+    api::notification::Notification::new(&window.app_handle().config().tauri.bundle.identifier)
+        .title(params.title)
+        .body(params.body)
+        .show()
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_info(app: AppHandle) -> AppInfo {
+    let package_info = app.package_info();
+    AppInfo {
+        name: package_info.name.clone(),
+        version: package_info.version.to_string(),
+        readonly_mode: app.state::<PakeConfig>().readonly_mode,
+    }
 }
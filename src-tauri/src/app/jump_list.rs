@@ -0,0 +1,63 @@
+use crate::app::config::JumpListTask;
+use std::env;
+use windows::core::{ComInterface, HSTRING, PWSTR};
+use windows::Win32::Storage::EnhancedStorage::PKEY_Title;
+use windows::Win32::System::Com::StructuredStorage::PROPVARIANT;
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED, VT_LPWSTR,
+};
+use windows::Win32::UI::Shell::Common::{IObjectArray, IObjectCollection};
+use windows::Win32::UI::Shell::PropertiesSystem::IPropertyStore;
+use windows::Win32::UI::Shell::{
+    DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW, ShellLink,
+};
+
+// Tasks start the app again with their args, which the running instance picks up.
+pub fn set_jump_list(tasks: &[JumpListTask]) {
+    if let Err(e) = unsafe { build_jump_list(tasks) } {
+        eprintln!("Failed to set the jump list: {}", e);
+    }
+}
+
+unsafe fn build_jump_list(tasks: &[JumpListTask]) -> windows::core::Result<()> {
+    // The window thread is usually initialized already, which is fine.
+    let _res = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+
+    let exe_path = env::current_exe().unwrap_or_default();
+    let exe_dir = exe_path
+        .parent()
+        .map(|dir| dir.to_path_buf())
+        .unwrap_or_default();
+    let list: ICustomDestinationList =
+        CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+    let mut min_slots = 0;
+    let _removed: IObjectArray = list.BeginList(&mut min_slots)?;
+
+    let collection: IObjectCollection =
+        CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+    for task in tasks {
+        let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+        link.SetPath(&HSTRING::from(exe_path.as_os_str()))?;
+        link.SetArguments(&HSTRING::from(task.args.as_str()))?;
+        if let Some(description) = &task.description {
+            link.SetDescription(&HSTRING::from(description.as_str()))?;
+        }
+        if let Some(icon) = &task.icon {
+            link.SetIconLocation(&HSTRING::from(exe_dir.join(icon).as_os_str()), 0)?;
+        }
+
+        // The title has to be set as a property, the buffer only needs to live until Commit.
+        let mut label: Vec<u16> = task.label.encode_utf16().chain(Some(0)).collect();
+        let mut title = PROPVARIANT::default();
+        (*title.Anonymous.Anonymous).vt = VT_LPWSTR;
+        (*title.Anonymous.Anonymous).Anonymous.pwszVal = PWSTR(label.as_mut_ptr());
+        let store: IPropertyStore = link.cast()?;
+        store.SetValue(&PKEY_Title, &title)?;
+        store.Commit()?;
+
+        collection.AddObject(&link)?;
+    }
+
+    list.AddUserTasks(&collection.cast::<IObjectArray>()?)?;
+    list.CommitList()
+}
@@ -1,49 +1,117 @@
+use crate::app::config::PakeConfig;
+use crate::app::window::{get_main_url, handle_close_shortcut, quit_app};
 use tauri::MenuItem;
 
-use tauri::{CustomMenuItem, Menu, Submenu, WindowMenuEvent};
+use tauri::{CustomMenuItem, Manager, Menu, Submenu, WindowMenuEvent};
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
-use tauri::{Manager, SystemTray, SystemTrayEvent, SystemTrayMenu, WindowBuilder, WindowUrl};
-
-#[cfg(any(target_os = "linux", target_os = "windows"))]
-use tauri_plugin_window_state::{AppHandleExt, StateFlags};
+use tauri::{SystemTray, SystemTrayEvent, SystemTrayMenu, WindowBuilder, WindowUrl};
 
 pub fn get_menu() -> Menu {
     let close = CustomMenuItem::new("close".to_string(), "Close Window").accelerator("CmdOrCtrl+W");
+    let quit = CustomMenuItem::new("quit".to_string(), "Quit").accelerator("CmdOrCtrl+Q");
     let goto_url_item = CustomMenuItem::new("goto_url".to_string(), "Go to URL...")
         .accelerator("CmdOrCtrl+Shift+L");
     let first_menu = Menu::new()
-        .add_native_item(MenuItem::Copy)
-        .add_native_item(MenuItem::Cut)
-        .add_native_item(MenuItem::Paste)
-        .add_native_item(MenuItem::Undo)
-        .add_native_item(MenuItem::Redo)
-        .add_native_item(MenuItem::SelectAll)
-        .add_native_item(MenuItem::Separator)
         .add_item(goto_url_item)
         .add_native_item(MenuItem::Separator)
-        .add_native_item(MenuItem::EnterFullScreen)
         .add_native_item(MenuItem::Minimize)
         .add_native_item(MenuItem::Hide)
         .add_native_item(MenuItem::HideOthers)
         .add_native_item(MenuItem::ShowAll)
         .add_native_item(MenuItem::Separator)
         .add_item(close)
-        .add_native_item(MenuItem::Quit);
+        .add_item(quit);
+
+    // Undo and Redo only exist on macOS, elsewhere the page handles those keys itself.
+    let edit_menu = Menu::new()
+        .add_native_item(MenuItem::Undo)
+        .add_native_item(MenuItem::Redo)
+        .add_native_item(MenuItem::Separator)
+        .add_native_item(MenuItem::Cut)
+        .add_native_item(MenuItem::Copy)
+        .add_native_item(MenuItem::Paste)
+        .add_native_item(MenuItem::SelectAll);
+
+    // Zoom and navigation keys are already handled by event.js, so those items have no accelerator.
+    let reload = CustomMenuItem::new("reload".to_string(), "Reload").accelerator("CmdOrCtrl+R");
+    let hard_reload = CustomMenuItem::new("hard_reload".to_string(), "Hard Reload")
+        .accelerator("CmdOrCtrl+Shift+R");
+    let view_menu = Menu::new()
+        .add_item(reload)
+        .add_item(hard_reload)
+        .add_native_item(MenuItem::Separator)
+        .add_item(CustomMenuItem::new("zoom_in".to_string(), "Zoom In"))
+        .add_item(CustomMenuItem::new("zoom_out".to_string(), "Zoom Out"))
+        .add_item(CustomMenuItem::new("zoom_reset".to_string(), "Actual Size"))
+        .add_native_item(MenuItem::Separator);
+    #[cfg(target_os = "macos")]
+    let view_menu = view_menu.add_native_item(MenuItem::EnterFullScreen);
+    #[cfg(not(target_os = "macos"))]
+    let view_menu = view_menu.add_item(
+        CustomMenuItem::new("toggle_fullscreen".to_string(), "Toggle Full Screen")
+            .accelerator("F11"),
+    );
+
+    let navigate_menu = Menu::new()
+        .add_item(CustomMenuItem::new("go_back".to_string(), "Back"))
+        .add_item(CustomMenuItem::new("go_forward".to_string(), "Forward"))
+        .add_item(CustomMenuItem::new("go_home".to_string(), "Home"));
 
-    let app_menu = Submenu::new("File", first_menu);
-    Menu::new().add_submenu(app_menu)
+    Menu::new()
+        .add_submenu(Submenu::new("File", first_menu))
+        .add_submenu(Submenu::new("Edit", edit_menu))
+        .add_submenu(Submenu::new("View", view_menu))
+        .add_submenu(Submenu::new("Navigate", navigate_menu))
 }
 
 pub fn menu_event_handle(event: WindowMenuEvent) {
-    if event.menu_item_id() == "close" {
-        event.window().minimize().expect("can't minimize window");
-        // event.window().eval("toggleVideoPlayback(true);").unwrap();
-    }
-
-    if event.menu_item_id() == "goto_url" {
-        let js_code = "showUrlModal();";
-        event.window().eval(js_code).unwrap();
+    let window = event.window();
+    match event.menu_item_id() {
+        "close" => {
+            handle_close_shortcut(window);
+            // window.eval("toggleVideoPlayback(true);").unwrap();
+        }
+        "goto_url" => {
+            let js_code = "showUrlModal();";
+            window.eval(js_code).unwrap();
+        }
+        "reload" => {
+            window.eval("window.location.reload();").unwrap();
+        }
+        "hard_reload" => {
+            let _res = window.eval("pakeHardReload();");
+        }
+        "zoom_in" => {
+            let _res = window.eval("zoomIn();");
+        }
+        "zoom_out" => {
+            let _res = window.eval("zoomOut();");
+        }
+        "zoom_reset" => {
+            let _res = window.eval("setZoom('100%');");
+        }
+        "toggle_fullscreen" => {
+            let fullscreen = window.is_fullscreen().unwrap_or(false);
+            let _res = window.set_fullscreen(!fullscreen);
+        }
+        "go_back" => {
+            let _res = window.eval("window.history.back();");
+        }
+        "go_forward" => {
+            let _res = window.eval("window.history.forward();");
+        }
+        "go_home" => {
+            let home_url = get_main_url(&window.state::<PakeConfig>()).to_string();
+            let _res = window.eval(&format!(
+                "window.location.href = {};",
+                serde_json::to_string(&home_url).unwrap()
+            ));
+        }
+        "quit" => {
+            quit_app(&window.app_handle());
+        }
+        _ => {}
     }
 }
 
@@ -71,6 +139,17 @@ pub fn get_system_tray(show_menu: bool) -> SystemTray {
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 pub fn system_tray_handle(app: &tauri::AppHandle, event: SystemTrayEvent) {
+    if let SystemTrayEvent::LeftClick { .. } = event {
+        let window = app.get_window("pake").unwrap();
+        if window.is_visible().unwrap() {
+            window.hide().unwrap();
+        } else {
+            window.show().unwrap();
+            window.set_focus().unwrap();
+        }
+        return;
+    }
+
     if let SystemTrayEvent::MenuItemClick { tray_id: _, id, .. } = event {
         match id.as_str() {
             "hide_app" => {
@@ -94,8 +173,7 @@ pub fn system_tray_handle(app: &tauri::AppHandle, event: SystemTrayEvent) {
                     .unwrap();
             }
             "quit" => {
-                let _res = app.save_window_state(StateFlags::all());
-                std::process::exit(0);
+                quit_app(app);
             }
             "about" => {
                 let _about_window = WindowBuilder::new(
@@ -1,4 +1,11 @@
+pub mod allowed_hours;
 pub mod config;
+pub mod downloads;
+pub mod instance;
 pub mod invoke;
+#[cfg(target_os = "windows")]
+pub mod jump_list;
 pub mod menu;
+pub mod state;
+pub mod trace;
 pub mod window;
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const STATE_FILENAME: &str = ".pake-state";
+const STATE_VERSION: u32 = 2;
+static NEXT_TMP_ID: AtomicUsize = AtomicUsize::new(1);
+
+// Settings the window-state plugin doesn't cover.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PakeState {
+    // Files written before versioning have no version and read as 0.
+    #[serde(default)]
+    pub version: u32,
+    pub zoom: f64,
+    pub monitor: Option<MonitorState>,
+    pub panels: Vec<PanelState>,
+    pub appearance: Option<AppearanceState>,
+    pub history: Vec<HistoryEntry>,
+    // Origins the user allowed to keep their storage, through navigator.storage.persist().
+    pub persistent_origins: Vec<String>,
+    // Whether the user allowed each origin to show notifications, they are asked once.
+    pub notification_origins: HashMap<String, bool>,
+    // Set once the main window is pinned or unpinned, and then wins over the config.
+    pub always_on_top: Option<bool>,
+    // Whether the main window had its title bar and borders, also wins over the config.
+    pub decorated: Option<bool>,
+    // Reopened on the next start, only where devtools are allowed.
+    pub devtools_open: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MonitorState {
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+}
+
+// Sizes and positions are logical, like the ones pages pass to open_panel.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PanelState {
+    pub url: String,
+    pub width: f64,
+    pub height: f64,
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub always_on_top: bool,
+    pub frameless: bool,
+}
+
+// How the page looked when it was last seen, painted before it loads again.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AppearanceState {
+    // Filled in by the app, the snapshot only applies while the configured url stays the same.
+    #[serde(default)]
+    pub url: String,
+    pub background: String,
+    pub direction: String,
+    pub color_scheme: String,
+    pub icon_hash: String,
+}
+
+// Visited pages of the main window, last_visit is in seconds since the Unix epoch.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: String,
+    pub visits: u32,
+    pub last_visit: u64,
+}
+
+impl Default for PakeState {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            zoom: 1.0,
+            monitor: None,
+            panels: Vec::new(),
+            appearance: None,
+            history: Vec::new(),
+            persistent_origins: Vec::new(),
+            notification_origins: HashMap::new(),
+            always_on_top: None,
+            decorated: None,
+            devtools_open: false,
+        }
+    }
+}
+
+// Missing fields already take their defaults, so older versions only need the bump.
+fn migrate_state(mut state: PakeState) -> PakeState {
+    if state.version < STATE_VERSION {
+        state.version = STATE_VERSION;
+    }
+    state
+}
+
+// The one copy of the state, held in the app's managed state. Every change is made and written
+// under its lock, so the windows, commands and background threads can't undo each other's.
+pub struct StateStore {
+    data_dir: PathBuf,
+    state: Mutex<PakeState>,
+}
+
+impl StateStore {
+    pub fn load(data_dir: &Path) -> Self {
+        Self {
+            data_dir: data_dir.to_path_buf(),
+            state: Mutex::new(load_state(data_dir)),
+        }
+    }
+
+    pub fn read<T>(&self, read: impl FnOnce(&PakeState) -> T) -> T {
+        read(&self.state.lock().unwrap())
+    }
+
+    pub fn update(&self, update: impl FnOnce(&mut PakeState)) {
+        let mut state = self.state.lock().unwrap();
+        update(&mut state);
+        save_state(&self.data_dir, &state);
+    }
+}
+
+// A missing or corrupt file falls back to the defaults.
+fn load_state(data_dir: &Path) -> PakeState {
+    fs::read_to_string(data_dir.join(STATE_FILENAME))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .map(migrate_state)
+        .unwrap_or_default()
+}
+
+// Write to a temporary file first, so a crash can't leave a truncated state file.
+// Its name is unique, so no other writer can rename a half-written one.
+fn save_state(data_dir: &Path, state: &PakeState) {
+    let path = data_dir.join(STATE_FILENAME);
+    let tmp_path = path.with_extension(format!(
+        "{}-{}.tmp",
+        process::id(),
+        NEXT_TMP_ID.fetch_add(1, Ordering::SeqCst)
+    ));
+    let content = serde_json::to_string(state).unwrap();
+    if fs::write(&tmp_path, content).is_err() || fs::rename(&tmp_path, path).is_err() {
+        let _res = fs::remove_file(tmp_path);
+    }
+}
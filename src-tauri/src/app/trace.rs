@@ -0,0 +1,156 @@
+use crate::util::devtools_requested;
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{InvokeMessage, Window, Wry};
+
+// Startup problems show up early, so only the first minute is recorded.
+const TRACE_DURATION: Duration = Duration::from_secs(60);
+const PAYLOAD_LIMIT: usize = 500;
+const REDACTED_KEYS: &[&str] = &["password", "token", "secret", "cookie", "authorization"];
+
+// Checked before taking the lock, so tracing costs nothing when it is off.
+static TRACING: AtomicBool = AtomicBool::new(false);
+static TRACE: Mutex<Option<(File, Instant)>> = Mutex::new(None);
+static REPLAYED: AtomicBool = AtomicBool::new(false);
+
+fn get_path_arg(flag: &str) -> Option<PathBuf> {
+    let mut args = env::args().skip_while(|arg| arg != flag);
+    args.next()?;
+    args.next().map(PathBuf::from)
+}
+
+// --trace <file> writes one JSON object per line.
+pub fn start_trace() {
+    let path = match get_path_arg("--trace") {
+        Some(path) => path,
+        None => return,
+    };
+    match File::create(&path) {
+        Ok(file) => {
+            *TRACE.lock().unwrap() = Some((file, Instant::now()));
+            TRACING.store(true, Ordering::SeqCst);
+        }
+        Err(e) => eprintln!("Failed to create the trace {}: {}", path.display(), e),
+    }
+}
+
+// The detail is only built while tracing.
+pub fn record(kind: &str, detail: impl FnOnce() -> Value) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut trace = TRACE.lock().unwrap();
+    let (file, started) = match trace.as_mut() {
+        Some(trace) => trace,
+        None => return,
+    };
+    let elapsed = started.elapsed();
+    if elapsed > TRACE_DURATION {
+        TRACING.store(false, Ordering::SeqCst);
+        *trace = None;
+        return;
+    }
+    let line = json!({ "ms": elapsed.as_millis() as u64, "kind": kind, "detail": detail() });
+    let _res = writeln!(file, "{}", line);
+}
+
+// Payloads that are too long to keep are only recorded as truncated text, and can't be replayed.
+pub fn record_invoke(message: &InvokeMessage<Wry>) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    let payload = redact(message.payload().clone());
+    let text = payload.to_string();
+    let detail = if text.len() > PAYLOAD_LIMIT {
+        let end = (0..=PAYLOAD_LIMIT)
+            .rev()
+            .find(|&end| text.is_char_boundary(end))
+            .unwrap_or(0);
+        json!({
+            "window": message.window().label(),
+            "command": message.command(),
+            "truncated": &text[..end],
+        })
+    } else {
+        json!({
+            "window": message.window().label(),
+            "command": message.command(),
+            "payload": payload,
+        })
+    };
+    record("ipc", || detail);
+}
+
+pub fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let lower_key = key.to_lowercase();
+                    if REDACTED_KEYS
+                        .iter()
+                        .any(|redacted| lower_key.contains(redacted))
+                    {
+                        (key, Value::String("[redacted]".to_string()))
+                    } else {
+                        (key, redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+        value => value,
+    }
+}
+
+// --replay <file> sends the recorded commands of the main window again, in order, once its
+// first page has loaded. Only in debug builds or with PAKE_DEVTOOLS=1.
+pub fn replay(window: &Window) {
+    let path = match get_path_arg("--replay") {
+        Some(path) => path,
+        None => return,
+    };
+    if REPLAYED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if !cfg!(debug_assertions) && !devtools_requested() {
+        eprintln!("Replaying a trace needs a debug build or PAKE_DEVTOOLS=1");
+        return;
+    }
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Failed to read the trace {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    let steps: Vec<Value> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|entry| entry["kind"] == "ipc" && entry["detail"]["window"] == "pake")
+        .filter_map(|entry| {
+            let detail = &entry["detail"];
+            Some(json!([detail["command"], detail.get("payload")?]))
+        })
+        .collect();
+
+    let _res = window.eval(&format!(
+        "(async () => {{
+            for (const [command, payload] of {}) {{
+                try {{
+                    console.log('replay', command, await invoke(command, payload));
+                }} catch (e) {{
+                    console.error('replay', command, e);
+                }}
+            }}
+        }})();",
+        Value::Array(steps)
+    ));
+}
@@ -1,35 +1,84 @@
-use crate::app::config::PakeConfig;
+use crate::app::config::{CloseBehavior, NewWindowMode, PakeConfig};
+use crate::app::state::{MonitorState, PanelState, StateStore};
+use crate::util::{get_data_dir, is_same_host, read_feature_flags, show_toast};
 use std::path::PathBuf;
-use tauri::{App, Window, WindowBuilder, WindowUrl};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{
+    App, AppHandle, GlobalShortcutManager, Icon, LogicalSize, Manager, PhysicalPosition,
+    PhysicalSize, Url, Window, WindowBuilder, WindowUrl, Wry,
+};
+use tauri_plugin_window_state::{AppHandleExt, StateFlags};
 
+#[cfg(target_os = "macos")]
+use crate::app::config::WindowTabbing;
 #[cfg(target_os = "macos")]
 use tauri::TitleBarStyle;
 
-pub fn get_window(app: &mut App, config: PakeConfig, _data_dir: PathBuf) -> Window {
+pub const PANEL_LABEL_PREFIX: &str = "panel-";
+const LINK_WINDOW_LABEL_PREFIX: &str = "window-";
+static NEXT_LINK_WINDOW_ID: AtomicUsize = AtomicUsize::new(1);
+pub const MANIFEST_ICON_FILENAME: &str = "manifest-icon.png";
+const POPUP_LABEL_PREFIX: &str = "popup-";
+static NEXT_POPUP_ID: AtomicUsize = AtomicUsize::new(1);
+const MAX_PANELS: usize = 4;
+static NEXT_PANEL_ID: AtomicUsize = AtomicUsize::new(1);
+// Windows can't report whether they are always on top, so remember the pinned ones by label.
+// The main window keeps its own, saved with the rest of its state.
+static PINNED_WINDOWS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+const SAVE_STATE_DELAY: Duration = Duration::from_millis(500);
+static LAST_GEOMETRY_CHANGE: Mutex<Option<Instant>> = Mutex::new(None);
+static SAVE_STATE_SCHEDULED: AtomicBool = AtomicBool::new(false);
+static QUIT_READY: AtomicBool = AtomicBool::new(false);
+static MAIN_ALWAYS_ON_TOP: AtomicBool = AtomicBool::new(false);
+
+const AUTO_FIT_MIN_SIZE: f64 = 100.0;
+// The size auto-fit last asked for, any other size means the user resized the window.
+static AUTO_FIT_SIZE: Mutex<Option<PhysicalSize<u32>>> = Mutex::new(None);
+static AUTO_FIT_PAUSED: AtomicBool = AtomicBool::new(false);
+
+pub fn get_window(app: &mut App, config: &PakeConfig, data_dir: PathBuf) -> Window {
     let window_config = config
         .windows
         .first()
         .expect("At least one window configuration is required");
 
-    let user_agent = config.user_agent.get();
-
     let url = match window_config.url_type.as_str() {
         "web" => WindowUrl::App(window_config.url.parse().unwrap()),
         "local" => WindowUrl::App(PathBuf::from(&window_config.url)),
         _ => panic!("url type can only be web or local"),
     };
 
-    let mut window_builder = WindowBuilder::new(app, "pake", url)
-        .title("")
-        .user_agent(user_agent)
+    let title = if config.pin_title {
+        app.package_info().name.clone()
+    } else {
+        String::new()
+    };
+
+    let (always_on_top, decorated) = app.state::<StateStore>().read(|state| {
+        (
+            state.always_on_top.unwrap_or(window_config.always_on_top),
+            state
+                .decorated
+                .unwrap_or_else(|| window_config.decorations.unwrap_or(true)),
+        )
+    });
+    MAIN_ALWAYS_ON_TOP.store(always_on_top, Ordering::SeqCst);
+
+    let mut window_builder = get_window_builder(app, "pake", url, config, data_dir.clone())
+        .title(title)
+        .always_on_top(always_on_top)
+        .decorations(decorated)
         .visible(false) // Prevent initial shaking
         .resizable(window_config.resizable)
         .fullscreen(window_config.fullscreen)
-        .inner_size(window_config.width, window_config.height)
-        .disable_file_drop_handler() //Very annoying, otherwise dragging files to the window will not work.
-        .initialization_script(include_str!("../inject/style.js"))
-        .initialization_script(include_str!("../inject/event.js"))
-        .initialization_script(include_str!("../inject/component.js"));
+        .inner_size(window_config.width, window_config.height);
+
+    if let Some((min_width, min_height)) = window_config.min_size() {
+        window_builder = window_builder.min_inner_size(min_width, min_height);
+    }
 
     #[cfg(target_os = "macos")]
     {
@@ -41,10 +90,938 @@ pub fn get_window(app: &mut App, config: PakeConfig, _data_dir: PathBuf) -> Wind
         window_builder = window_builder.title_bar_style(title_bar_style)
     }
 
+    let window = window_builder.build().unwrap();
+    // The window-state plugin restores its own copy of the decorations, which may be older.
+    let _res = window.set_decorations(decorated);
+    restore_monitor(&window);
+    keep_on_screen(&window);
+    if let Some(min_size) = window_config.min_size() {
+        keep_min_size(&window, min_size);
+    }
+    #[cfg(any(debug_assertions, feature = "devtools"))]
+    if crate::util::devtools_requested()
+        || (cfg!(debug_assertions) && app.state::<StateStore>().read(|state| state.devtools_open))
+    {
+        window.open_devtools();
+    }
+
+    // The icon from the last web manifest shows until the page sends a fresh one.
+    if config.read_web_manifest {
+        if let Ok(icon) = std::fs::read(data_dir.join(MANIFEST_ICON_FILENAME)) {
+            let _res = window.set_icon(Icon::Raw(icon));
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    if window_config.transparent && config.backdrop_filter.is_some() {
+        set_acrylic_backdrop(&window);
+    }
+
+    #[cfg(target_os = "macos")]
+    if let Some(tabbing) = &window_config.window_tabbing {
+        set_tabbing_mode(&window, tabbing);
+    }
+
+    window
+}
+
+// Every webview window shares the same scripts, user agent and storage.
+pub fn get_window_builder<'a, M: Manager<Wry>>(
+    manager: &'a M,
+    label: &str,
+    url: WindowUrl,
+    config: &PakeConfig,
+    data_dir: PathBuf,
+) -> WindowBuilder<'a, Wry> {
+    let user_agent = config.user_agent.get();
+
+    let appearance = manager
+        .state::<StateStore>()
+        .read(|state| state.appearance.clone())
+        .filter(|appearance| appearance.url == get_main_url(config));
+
+    // Expose the config and the last appearance to the injected scripts.
+    // Anything that changes while the app runs, like the zoom, is asked for on each page load.
+    // The platform is "macos", "windows" or "linux", so scripts don't guess from the user agent.
+    let mut config_value = serde_json::to_value(config).unwrap();
+    config_value["platform"] = std::env::consts::OS.into();
+    let config_script = format!(
+        "window.pakeConfig = {}; window.pakeState = {}; window.PAKE_FLAGS = {};",
+        config_value,
+        serde_json::json!({ "appearance": appearance }),
+        serde_json::to_string(&read_feature_flags(config)).unwrap()
+    );
+
+    let mut window_builder = WindowBuilder::new(manager, label, url)
+        .user_agent(user_agent)
+        .disable_file_drop_handler() //Very annoying, otherwise dragging files to the window will not work.
+        .initialization_script(&config_script)
+        .initialization_script(include_str!("../inject/style.js"))
+        .initialization_script(include_str!("../inject/event.js"))
+        .initialization_script(include_str!("../inject/component.js"));
+
+    if let Some(css) = get_custom_css(config) {
+        window_builder = window_builder.initialization_script(&css);
+    }
+
+    if let Some(script) = get_network_simulation_script(config) {
+        window_builder = window_builder.initialization_script(&script);
+    }
+
     #[cfg(not(target_os = "macos"))]
     {
-        window_builder = window_builder.data_directory(_data_dir);
+        window_builder = window_builder.data_directory(data_dir);
+    }
+
+    window_builder
+}
+
+pub fn get_main_url(config: &PakeConfig) -> &str {
+    &config
+        .windows
+        .first()
+        .expect("At least one window configuration is required")
+        .url
+}
+
+// Delays and drops fetch calls to mimic a slow network while developing.
+fn get_network_simulation_script(config: &PakeConfig) -> Option<String> {
+    let latency = config.network_latency_ms.unwrap_or(0);
+    let jitter = config.network_latency_jitter_ms.unwrap_or(0);
+    let packet_loss = config.network_packet_loss_percent.unwrap_or(0).min(100);
+    if !cfg!(debug_assertions) || (latency == 0 && jitter == 0 && packet_loss == 0) {
+        return None;
+    }
+
+    Some(format!(
+        "(() => {{
+            const originalFetch = window.fetch;
+            window.fetch = async (...args) => {{
+                const delay = {} + Math.random() * {};
+                await new Promise((resolve) => setTimeout(resolve, delay));
+                if (Math.random() * 100 < {}) {{
+                    throw new TypeError('Failed to fetch (simulated packet loss)');
+                }}
+                return originalFetch(...args);
+            }};
+        }})();",
+        latency, jitter, packet_loss
+    ))
+}
+
+// A missing custom_css file is skipped, so the page still loads.
+fn get_custom_css(config: &PakeConfig) -> Option<String> {
+    let css = std::fs::read_to_string(config.custom_css.as_ref()?).ok()?;
+    Some(format!(
+        "document.addEventListener('DOMContentLoaded', () => {{
+            const style = document.createElement('style');
+            style.textContent = {};
+            document.head.appendChild(style);
+        }});",
+        serde_json::to_string(&css).unwrap()
+    ))
+}
+
+// Everything that asks to close the main window goes through here.
+pub fn request_close_main_window(window: &Window) {
+    // Keep running in the background, the tray or the Quit menu item can still quit.
+    if window.state::<PakeConfig>().hide_on_close {
+        let _res = window.app_handle().save_window_state(StateFlags::all());
+        window.hide().unwrap();
+        return;
+    }
+
+    // With the tray enabled the app keeps running in the background.
+    #[cfg(not(target_os = "macos"))]
+    if window.state::<PakeConfig>().enable_tray {
+        window.hide().unwrap();
+        return;
     }
 
-    window_builder.build().unwrap()
+    // Closing unloads the page here, so let its beforeunload handler have a say.
+    #[cfg(not(target_os = "macos"))]
+    if window.state::<PakeConfig>().respect_before_unload {
+        window.eval("pakeCheckBeforeUnload();").unwrap();
+        return;
+    }
+
+    close_main_window(window);
+}
+
+// Closing the main window minimizes it on macOS and quits elsewhere.
+// Minimizes by default, a hidden window comes back from the tray or by launching the app again.
+pub fn handle_close_shortcut(window: &Window) {
+    if window.label() != "pake" {
+        let _res = window.close();
+        return;
+    }
+    match window.state::<PakeConfig>().cmd_close_behavior {
+        None | Some(CloseBehavior::Minimize) => {
+            let _res = window.minimize();
+        }
+        Some(CloseBehavior::Hide) => {
+            let _res = window.app_handle().save_window_state(StateFlags::all());
+            let _res = window.hide();
+        }
+        Some(CloseBehavior::Quit) => quit_app(&window.app_handle()),
+    }
+}
+
+pub fn close_main_window(window: &Window) {
+    #[cfg(target_os = "macos")]
+    {
+        window.minimize().unwrap();
+        // window.eval("toggleVideoPlayback(true);").unwrap();
+    }
+
+    // Panels and link windows go away with the main window.
+    #[cfg(not(target_os = "macos"))]
+    quit_app(&window.app_handle());
+}
+
+pub fn open_panel_window(app: &AppHandle, panel: &PanelState) -> Result<String, String> {
+    let config = app.state::<PakeConfig>();
+    if !config.allow_panels {
+        return Err("Panels are not enabled".to_string());
+    }
+
+    let url: Url = panel.url.parse().map_err(|_| "Invalid panel url")?;
+    if !is_same_host(&config, &url) {
+        return Err("Panels can only open pages of the same host".to_string());
+    }
+
+    let panel_count = app
+        .windows()
+        .keys()
+        .filter(|label| label.starts_with(PANEL_LABEL_PREFIX))
+        .count();
+    if panel_count >= MAX_PANELS {
+        return Err(format!("At most {} panels can be open", MAX_PANELS));
+    }
+
+    let label = format!(
+        "{}{}",
+        PANEL_LABEL_PREFIX,
+        NEXT_PANEL_ID.fetch_add(1, Ordering::SeqCst)
+    );
+    let data_dir = get_data_dir(&app.config());
+    let mut window_builder =
+        get_window_builder(app, &label, WindowUrl::External(url), &config, data_dir)
+            .title("")
+            .inner_size(panel.width, panel.height)
+            .always_on_top(panel.always_on_top)
+            .decorations(!panel.frameless);
+    if let (Some(x), Some(y)) = (panel.x, panel.y) {
+        window_builder = window_builder.position(x, y);
+    }
+    window_builder.build().map_err(|e| e.to_string())?;
+
+    if panel.always_on_top {
+        PINNED_WINDOWS.lock().unwrap().push(label.clone());
+    }
+    Ok(label)
+}
+
+// Same-host links that ask for a new window get a regular app window.
+pub fn open_link_window(app: &AppHandle, url: &str) -> Result<String, String> {
+    let config = app.state::<PakeConfig>();
+    if !config.open_blank_in_new_window {
+        return Err("Opening links in new windows is not enabled".to_string());
+    }
+
+    let url: Url = url.parse().map_err(|_| "Invalid window url")?;
+    if !is_same_host(&config, &url) {
+        return Err("Only pages of the same host open in a new window".to_string());
+    }
+
+    let window_config = config
+        .windows
+        .first()
+        .expect("At least one window configuration is required");
+    let label = format!(
+        "{}{}",
+        LINK_WINDOW_LABEL_PREFIX,
+        NEXT_LINK_WINDOW_ID.fetch_add(1, Ordering::SeqCst)
+    );
+    let data_dir = get_data_dir(&app.config());
+    get_window_builder(app, &label, WindowUrl::External(url), &config, data_dir)
+        .title("")
+        .resizable(window_config.resizable)
+        .inner_size(window_config.width, window_config.height)
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(label)
+}
+
+// Popups of any host, like OAuth logins, when new_window_mode is child-window.
+// They are sized and centered every time, so a stale window-state entry never applies.
+// They only share the user agent and storage, none of the scripts, config or commands.
+pub fn open_popup_window(
+    app: &AppHandle,
+    url: &str,
+    width: f64,
+    height: f64,
+) -> Result<String, String> {
+    let config = app.state::<PakeConfig>();
+    if config.new_window_mode != Some(NewWindowMode::ChildWindow) {
+        return Err("Opening popups in the app is not enabled".to_string());
+    }
+
+    let url: Url = url.parse().map_err(|_| "Invalid popup url")?;
+    let label = format!(
+        "{}{}",
+        POPUP_LABEL_PREFIX,
+        NEXT_POPUP_ID.fetch_add(1, Ordering::SeqCst)
+    );
+    let window_builder = WindowBuilder::new(app, &label, WindowUrl::External(url))
+        .user_agent(config.user_agent.get())
+        .disable_file_drop_handler();
+    #[cfg(not(target_os = "macos"))]
+    let window_builder = window_builder.data_directory(get_data_dir(&app.config()));
+    let window = window_builder
+        .title("")
+        .inner_size(width, height)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let _res = window.set_size(LogicalSize::new(width, height));
+    let _res = window.center();
+    Ok(label)
+}
+
+pub fn is_popup_window(label: &str) -> bool {
+    label.starts_with(POPUP_LABEL_PREFIX)
+}
+
+// Remember the open panels, so the next launch can bring them back.
+pub fn save_session(app: &AppHandle) {
+    let pinned_windows = PINNED_WINDOWS.lock().unwrap();
+    let panels = app
+        .windows()
+        .into_iter()
+        .filter(|(label, _)| label.starts_with(PANEL_LABEL_PREFIX))
+        .filter_map(|(label, window)| {
+            let scale_factor = window.scale_factor().ok()?;
+            let size = window.inner_size().ok()?.to_logical::<f64>(scale_factor);
+            let position = window
+                .outer_position()
+                .ok()?
+                .to_logical::<f64>(scale_factor);
+            Some(PanelState {
+                url: window.url().to_string(),
+                width: size.width,
+                height: size.height,
+                x: Some(position.x),
+                y: Some(position.y),
+                always_on_top: pinned_windows.contains(&label),
+                frameless: !window.is_decorated().unwrap_or(true),
+            })
+        })
+        .collect();
+
+    app.state::<StateStore>()
+        .update(|state| state.panels = panels);
+}
+
+// One panel failing to open doesn't stop the others.
+pub fn restore_session(app: &AppHandle) {
+    // Holding Shift at launch skips the restore, in case a panel breaks the app.
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    if is_shift_pressed() {
+        return;
+    }
+
+    let panels = app.state::<StateStore>().read(|state| state.panels.clone());
+    for panel in panels {
+        if let Err(e) = open_panel_window(app, &panel) {
+            eprintln!("Failed to restore panel {}: {}", panel.url, e);
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn is_shift_pressed() -> bool {
+    use objc::{class, msg_send, sel, sel_impl};
+
+    // NSEventModifierFlagShift
+    let flags: usize = unsafe { msg_send![class!(NSEvent), modifierFlags] };
+    flags & (1 << 17) != 0
+}
+
+#[cfg(target_os = "windows")]
+fn is_shift_pressed() -> bool {
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_SHIFT};
+
+    // The high bit is set while the key is down.
+    let state = unsafe { GetAsyncKeyState(VK_SHIFT as i32) };
+    state < 0
+}
+
+// Save the window state once the window stops moving, so it survives a crash or kill.
+pub fn schedule_save_window_state(app: AppHandle) {
+    *LAST_GEOMETRY_CHANGE.lock().unwrap() = Some(Instant::now());
+    if SAVE_STATE_SCHEDULED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SAVE_STATE_DELAY);
+        let last_change = LAST_GEOMETRY_CHANGE.lock().unwrap().unwrap();
+        if last_change.elapsed() >= SAVE_STATE_DELAY {
+            SAVE_STATE_SCHEDULED.store(false, Ordering::SeqCst);
+            let _res = app.save_window_state(StateFlags::all());
+            save_monitor(&app);
+            save_decorations(&app);
+            break;
+        }
+    });
+}
+
+fn save_monitor(app: &AppHandle) {
+    let monitor = match app
+        .get_window("pake")
+        .map(|window| window.current_monitor())
+    {
+        Some(Ok(Some(monitor))) => monitor,
+        _ => return,
+    };
+
+    app.state::<StateStore>().update(|state| {
+        state.monitor = Some(MonitorState {
+            name: monitor.name().cloned(),
+            x: monitor.position().x,
+            y: monitor.position().y,
+        })
+    });
+}
+
+// Pages can drop or bring back the title bar through the window API, keep what they chose.
+fn save_decorations(app: &AppHandle) {
+    let decorated = match app.get_window("pake").map(|window| window.is_decorated()) {
+        Some(Ok(decorated)) => decorated,
+        _ => return,
+    };
+
+    // Until the page changes them, the decorations keep following the config.
+    let configured = app
+        .state::<PakeConfig>()
+        .windows
+        .first()
+        .and_then(|window_config| window_config.decorations)
+        .unwrap_or(true);
+    let store = app.state::<StateStore>();
+    if store.read(|state| state.decorated.unwrap_or(configured) != decorated) {
+        store.update(|state| state.decorated = Some(decorated));
+    }
+}
+
+// Devtools dock inside the window or get their own, so the saved geometry never includes them.
+#[cfg(any(debug_assertions, feature = "devtools"))]
+fn save_devtools_open(app: &AppHandle) {
+    let devtools_open = match app.get_window("pake") {
+        Some(window) => window.is_devtools_open(),
+        None => return,
+    };
+
+    let store = app.state::<StateStore>();
+    if store.read(|state| state.devtools_open != devtools_open) {
+        store.update(|state| state.devtools_open = devtools_open);
+    }
+}
+
+// Only trust the restored position while the monitor it was saved on is still connected.
+// A fullscreen window is moved onto that monitor first, so it doesn't fill another display.
+fn restore_monitor(window: &Window) {
+    let saved_monitor = match window
+        .state::<StateStore>()
+        .read(|state| state.monitor.clone())
+    {
+        Some(monitor) => monitor,
+        None => return,
+    };
+
+    let connected_monitor = window
+        .available_monitors()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|monitor| {
+            monitor.name() == saved_monitor.name.as_ref()
+                && monitor.position().x == saved_monitor.x
+                && monitor.position().y == saved_monitor.y
+        });
+
+    let target_monitor = match connected_monitor {
+        Some(monitor) => monitor,
+        None => match window.primary_monitor() {
+            Ok(Some(primary_monitor)) => {
+                let _res = window.set_position(*primary_monitor.position());
+                let _res = window.center();
+                primary_monitor
+            }
+            _ => return,
+        },
+    };
+
+    if window.is_fullscreen().unwrap_or(false) {
+        let _res = window.set_fullscreen(false);
+        let _res = window.set_position(*target_monitor.position());
+        let _res = window.set_fullscreen(true);
+    }
+}
+
+// The window-state plugin applies the saved position as is, even when no monitor shows it anymore.
+// Returns whether the window had to move, a fullscreen window stays fullscreen.
+fn keep_on_screen(window: &Window) -> bool {
+    let (position, size) = match (window.outer_position(), window.outer_size()) {
+        (Ok(position), Ok(size)) => (position, size),
+        _ => return false,
+    };
+    let on_screen = window
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .any(|monitor| is_visible_on(position, size, *monitor.position(), *monitor.size()));
+    if on_screen {
+        return false;
+    }
+    let primary_monitor = match window.primary_monitor() {
+        Ok(Some(primary_monitor)) => primary_monitor,
+        _ => return false,
+    };
+    if window.is_fullscreen().unwrap_or(false) {
+        let _res = window.set_fullscreen(false);
+        let _res = window.set_position(*primary_monitor.position());
+        let _res = window.set_fullscreen(true);
+    } else {
+        let _res = window.set_position(*primary_monitor.position());
+        let _res = window.center();
+    }
+    true
+}
+
+// A size saved before the minimum was configured is still restored by the window-state plugin.
+fn keep_min_size(window: &Window, (min_width, min_height): (f64, f64)) {
+    let (scale_factor, size) = match (window.scale_factor(), window.inner_size()) {
+        (Ok(scale_factor), Ok(size)) => (scale_factor, size.to_logical::<f64>(scale_factor)),
+        _ => return,
+    };
+    if size.width < min_width || size.height < min_height {
+        let _res = window.set_size(LogicalSize::new(
+            size.width.max(min_width),
+            size.height.max(min_height),
+        ));
+    }
+}
+
+const MONITOR_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// Tauri has no event for displays coming and going, so compare the connected ones now and then.
+// When the display showing the main window goes away, the window moves to the primary one.
+pub fn watch_monitors(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut last_monitors = Vec::new();
+        loop {
+            let window = match app.get_window("pake") {
+                Some(window) => window,
+                None => return,
+            };
+            let monitors: Vec<_> = window
+                .available_monitors()
+                .unwrap_or_default()
+                .iter()
+                .map(|monitor| {
+                    (
+                        monitor.name().cloned(),
+                        *monitor.position(),
+                        *monitor.size(),
+                    )
+                })
+                .collect();
+            if !last_monitors.is_empty() && monitors != last_monitors && keep_on_screen(&window) {
+                let _res = window.emit("pake-display-changed", ());
+                show_toast(
+                    &window,
+                    "The display was disconnected, moved to the main display",
+                );
+            }
+            last_monitors = monitors;
+            std::thread::sleep(MONITOR_POLL_INTERVAL);
+        }
+    });
+}
+
+// Enough of the window has to show on the monitor to grab it, so a maximized window whose
+// borders hang past the edges, or one snapped to an edge, stays where it is.
+const MIN_VISIBLE_OVERLAP: i64 = 64;
+
+fn is_visible_on(
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+    monitor_position: PhysicalPosition<i32>,
+    monitor_size: PhysicalSize<u32>,
+) -> bool {
+    let overlap = |start: i32, length: u32, monitor_start: i32, monitor_length: u32| {
+        let end = i64::from(start) + i64::from(length);
+        let monitor_end = i64::from(monitor_start) + i64::from(monitor_length);
+        let visible = end.min(monitor_end) - i64::from(start).max(i64::from(monitor_start));
+        visible >= MIN_VISIBLE_OVERLAP.min(i64::from(length))
+    };
+    overlap(
+        position.x,
+        size.width,
+        monitor_position.x,
+        monitor_size.width,
+    ) && overlap(
+        position.y,
+        size.height,
+        monitor_position.y,
+        monitor_size.height,
+    )
+}
+
+const SHAKE_OFFSET: i32 = 20;
+const SHAKE_CYCLES: u32 = 4;
+const SHAKE_CYCLE: Duration = Duration::from_millis(50);
+static SHAKING: AtomicBool = AtomicBool::new(false);
+
+// Nudge the window left and right like a rejected password, then put it back.
+pub fn shake_window(window: &Window) {
+    if window.is_fullscreen().unwrap_or(false) || SHAKING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let origin = match window.outer_position() {
+        Ok(position) => position,
+        Err(_) => {
+            SHAKING.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+    let window = window.clone();
+    std::thread::spawn(move || {
+        for _ in 0..SHAKE_CYCLES {
+            for offset in [-SHAKE_OFFSET, SHAKE_OFFSET] {
+                let _res = window.set_position(PhysicalPosition::new(origin.x + offset, origin.y));
+                std::thread::sleep(SHAKE_CYCLE / 2);
+            }
+        }
+        let _res = window.set_position(origin);
+        SHAKING.store(false, Ordering::SeqCst);
+    });
+}
+
+// Windows can't report whether they are always on top, so the main window's pin is tracked here.
+pub fn is_main_always_on_top() -> bool {
+    MAIN_ALWAYS_ON_TOP.load(Ordering::SeqCst)
+}
+
+pub fn is_window_pinned(label: &str) -> bool {
+    if label == "pake" {
+        return is_main_always_on_top();
+    }
+    PINNED_WINDOWS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|pinned| pinned == label)
+}
+
+pub fn set_main_always_on_top(window: &Window, always_on_top: bool) -> Result<(), String> {
+    window
+        .set_always_on_top(always_on_top)
+        .map_err(|e| e.to_string())?;
+    MAIN_ALWAYS_ON_TOP.store(always_on_top, Ordering::SeqCst);
+
+    window
+        .state::<StateStore>()
+        .update(|state| state.always_on_top = Some(always_on_top));
+    Ok(())
+}
+
+// Panels and link windows each keep their own pin, a panel's is saved with the session.
+pub fn toggle_window_always_on_top(window: &Window) -> Result<bool, String> {
+    let label = window.label().to_string();
+    let always_on_top = {
+        let mut pinned_windows = PINNED_WINDOWS.lock().unwrap();
+        let always_on_top = !pinned_windows.contains(&label);
+        window
+            .set_always_on_top(always_on_top)
+            .map_err(|e| e.to_string())?;
+        if always_on_top {
+            pinned_windows.push(label.clone());
+        } else {
+            pinned_windows.retain(|pinned| *pinned != label);
+        }
+        always_on_top
+    };
+    if label.starts_with(PANEL_LABEL_PREFIX) {
+        save_session(&window.app_handle());
+    }
+    Ok(always_on_top)
+}
+
+// Windows 11 blurs the desktop behind the window, older versions ignore the attribute.
+// macOS and Linux only get the CSS filter, how it blends with the desktop depends on the compositor.
+#[cfg(target_os = "windows")]
+fn set_acrylic_backdrop(window: &Window) {
+    use windows_sys::Win32::Graphics::Dwm::{DwmSetWindowAttribute, DWMWA_SYSTEMBACKDROP_TYPE};
+
+    const DWMSBT_TRANSIENTWINDOW: i32 = 3;
+    if let Ok(hwnd) = window.hwnd() {
+        unsafe {
+            DwmSetWindowAttribute(
+                hwnd.0,
+                DWMWA_SYSTEMBACKDROP_TYPE,
+                &DWMSBT_TRANSIENTWINDOW as *const i32 as *const _,
+                std::mem::size_of::<i32>() as u32,
+            );
+        }
+    }
+}
+
+// CSS pixels match logical pixels at 100% page zoom, Tauri applies the scale factor.
+fn css_to_logical(css_pixels: f64, zoom: f64) -> f64 {
+    css_pixels * zoom
+}
+
+// The top-left corner stays put, the window grows at most to the edge of its monitor.
+pub fn fit_window_to_content(
+    window: &Window,
+    width: f64,
+    height: f64,
+    zoom: f64,
+) -> Result<(), String> {
+    if AUTO_FIT_PAUSED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let scale_factor = window.scale_factor().map_err(|e| e.to_string())?;
+    let position = window
+        .outer_position()
+        .map_err(|e| e.to_string())?
+        .to_logical::<f64>(scale_factor);
+    let (max_width, max_height) = match window.current_monitor() {
+        Ok(Some(monitor)) => {
+            let origin = monitor.position().to_logical::<f64>(scale_factor);
+            let size = monitor.size().to_logical::<f64>(scale_factor);
+            (
+                origin.x + size.width - position.x,
+                origin.y + size.height - position.y,
+            )
+        }
+        _ => (f64::MAX, f64::MAX),
+    };
+    let size = LogicalSize::new(
+        css_to_logical(width, zoom).clamp(AUTO_FIT_MIN_SIZE, max_width.max(AUTO_FIT_MIN_SIZE)),
+        css_to_logical(height, zoom).clamp(AUTO_FIT_MIN_SIZE, max_height.max(AUTO_FIT_MIN_SIZE)),
+    );
+    *AUTO_FIT_SIZE.lock().unwrap() = Some(size.to_physical(scale_factor));
+    window.set_size(size).map_err(|e| e.to_string())
+}
+
+// Within the configured minimum size and the size of the monitor.
+pub fn step_window_size(
+    window: &Window,
+    width_steps: f64,
+    height_steps: f64,
+) -> Result<(), String> {
+    let config = window.state::<PakeConfig>();
+    let step = config
+        .resize_step
+        .as_ref()
+        .ok_or("No resize_step is configured")?;
+    let (min_width, min_height) = config
+        .windows
+        .first()
+        .and_then(|window_config| window_config.min_size())
+        .unwrap_or((AUTO_FIT_MIN_SIZE, AUTO_FIT_MIN_SIZE));
+    let scale_factor = window.scale_factor().map_err(|e| e.to_string())?;
+    let size = window
+        .inner_size()
+        .map_err(|e| e.to_string())?
+        .to_logical::<f64>(scale_factor);
+    let (max_width, max_height) = match window.current_monitor() {
+        Ok(Some(monitor)) => {
+            let monitor_size = monitor.size().to_logical::<f64>(scale_factor);
+            (monitor_size.width, monitor_size.height)
+        }
+        _ => (f64::MAX, f64::MAX),
+    };
+
+    let width = (size.width + width_steps * step.width).clamp(min_width, max_width.max(min_width));
+    let height =
+        (size.height + height_steps * step.height).clamp(min_height, max_height.max(min_height));
+    window
+        .set_size(LogicalSize::new(width, height))
+        .map_err(|e| e.to_string())?;
+    window
+        .eval(&format!(
+            "window.pakeResized && window.pakeResized({{ width: {}, height: {} }});",
+            width, height
+        ))
+        .map_err(|e| e.to_string())
+}
+
+// A resize auto-fit didn't ask for turns it off until refit_window.
+pub fn note_main_window_resize(size: PhysicalSize<u32>) {
+    let expected = *AUTO_FIT_SIZE.lock().unwrap();
+    if let Some(expected) = expected {
+        if expected.width.abs_diff(size.width) > 1 || expected.height.abs_diff(size.height) > 1 {
+            AUTO_FIT_PAUSED.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+pub fn resume_auto_fit(window: &Window) -> Result<(), String> {
+    AUTO_FIT_PAUSED.store(false, Ordering::SeqCst);
+    window
+        .eval("window.pakeAutoFit && window.pakeAutoFit();")
+        .map_err(|e| e.to_string())
+}
+
+// Media keys are only delivered as global shortcuts, so forward them to the page as key events.
+pub fn register_media_keys(app: &App) {
+    let mut shortcut_manager = app.global_shortcut_manager();
+    for key in [
+        "MediaPlayPause",
+        "MediaTrackNext",
+        "MediaTrackPrevious",
+        "MediaStop",
+    ] {
+        let app = app.handle();
+        let script = format!(
+            "document.dispatchEvent(new KeyboardEvent('keydown', {{ key: '{}' }}));",
+            key
+        );
+        let result = shortcut_manager.register(key, move || {
+            if let Some(window) = app.get_window("pake") {
+                let _res = window.eval(&script);
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Failed to register the {} key: {}", key, e);
+        }
+    }
+}
+
+// Give the page a bounded time to flush its storage before the process exits.
+pub fn quit_app(app: &AppHandle) {
+    let _res = app.save_window_state(StateFlags::all());
+    save_decorations(app);
+    #[cfg(any(debug_assertions, feature = "devtools"))]
+    save_devtools_open(app);
+    save_session(app);
+    let timeout = app.state::<PakeConfig>().quit_timeout_ms.unwrap_or(1500);
+    let app = app.clone();
+
+    std::thread::spawn(move || {
+        let start = Instant::now();
+        let _res = app.emit_all("pake-before-quit", ());
+        while !is_quit_wait_over(
+            QUIT_READY.load(Ordering::SeqCst),
+            start.elapsed(),
+            Duration::from_millis(timeout),
+        ) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        // Unlike process::exit, this lets the webviews shut down properly.
+        app.exit(0);
+    });
+}
+
+pub fn mark_quit_ready() {
+    QUIT_READY.store(true, Ordering::SeqCst);
+}
+
+fn is_quit_wait_over(ready: bool, elapsed: Duration, timeout: Duration) -> bool {
+    ready || elapsed >= timeout
+}
+
+#[cfg(target_os = "macos")]
+fn set_tabbing_mode(window: &Window, tabbing: &WindowTabbing) {
+    use objc::runtime::Object;
+    use objc::{msg_send, sel, sel_impl};
+
+    // Values of NSWindowTabbingMode.
+    let mode: isize = match tabbing {
+        WindowTabbing::Default => 0,
+        WindowTabbing::Preferred => 1,
+        WindowTabbing::Disallowed => 2,
+    };
+    let ns_window = window.ns_window().unwrap() as *mut Object;
+    unsafe {
+        let _: () = msg_send![ns_window, setTabbingMode: mode];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONITOR_POSITION: PhysicalPosition<i32> = PhysicalPosition { x: 0, y: 0 };
+    const MONITOR_SIZE: PhysicalSize<u32> = PhysicalSize {
+        width: 1920,
+        height: 1080,
+    };
+
+    fn is_visible(x: i32, y: i32, width: u32, height: u32) -> bool {
+        is_visible_on(
+            PhysicalPosition::new(x, y),
+            PhysicalSize::new(width, height),
+            MONITOR_POSITION,
+            MONITOR_SIZE,
+        )
+    }
+
+    #[test]
+    fn maximized_window_stays_on_screen() {
+        assert!(is_visible(-8, -8, 1936, 1056));
+    }
+
+    #[test]
+    fn window_at_an_edge_stays_on_screen() {
+        assert!(is_visible(960, 0, 960, 1080));
+        assert!(is_visible(1920 - 100, 1080 - 100, 800, 600));
+        assert!(is_visible(-700, 200, 800, 600));
+    }
+
+    #[test]
+    fn window_off_screen_is_moved() {
+        assert!(!is_visible(2000, 100, 800, 600));
+        assert!(!is_visible(100, -700, 800, 600));
+        assert!(!is_visible(1920 - 20, 100, 800, 600));
+    }
+
+    #[test]
+    fn small_window_only_needs_to_show_fully() {
+        assert!(is_visible(1920 - 40, 0, 40, 40));
+        assert!(!is_visible(1920 - 39, 0, 40, 40));
+    }
+
+    #[test]
+    fn window_on_a_monitor_left_of_the_primary_one() {
+        assert!(is_visible_on(
+            PhysicalPosition::new(-1500, 100),
+            PhysicalSize::new(800, 600),
+            PhysicalPosition::new(-1920, 0),
+            PhysicalSize::new(1920, 1080),
+        ));
+    }
+
+    #[test]
+    fn quit_waits_until_the_page_is_ready() {
+        let timeout = Duration::from_millis(1500);
+        assert!(!is_quit_wait_over(false, Duration::ZERO, timeout));
+        assert!(!is_quit_wait_over(
+            false,
+            Duration::from_millis(1499),
+            timeout
+        ));
+        assert!(is_quit_wait_over(true, Duration::from_millis(10), timeout));
+    }
+
+    #[test]
+    fn quit_stops_waiting_after_the_timeout() {
+        let timeout = Duration::from_millis(1500);
+        assert!(is_quit_wait_over(false, timeout, timeout));
+        assert!(is_quit_wait_over(false, Duration::from_secs(5), timeout));
+        assert!(is_quit_wait_over(false, Duration::ZERO, Duration::ZERO));
+    }
 }
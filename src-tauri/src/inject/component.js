@@ -1,10 +1,10 @@
 document.addEventListener('DOMContentLoaded', () => {
   // Create a modal
   const modalHtml = `
-  <div id="pakeUrlModal" class="pake-modal">
+  <div id="pakeUrlModal" class="pake-modal" role="dialog" aria-modal="true" aria-labelledby="pakeUrlLabel">
     <div class="pake-modal-container">
       <div class="pake-modal-content">
-        <label for="pakeUrlInput">Enter URL to navigate anywhere</label>
+        <label id="pakeUrlLabel" for="pakeUrlInput">Enter URL to navigate anywhere</label>
         <input type="text" id="pakeUrlInput" />
         <button id="pakeUrlSubmit">Submit</button>
         <button id="pakeUrlClose">Close</button>
@@ -81,6 +81,78 @@ document.addEventListener('DOMContentLoaded', () => {
     text-align: left;
     min-height: 30px;
   }
+
+  #pakeSwitcherInput {
+    width: 100%;
+    box-sizing: border-box;
+    padding: 8px;
+    border: 1px solid #ccc;
+    border-radius: 4px;
+    font-size: 14px;
+    outline: none;
+  }
+
+  #pakeSwitcherList {
+    list-style: none;
+    margin: 8px 0 0;
+    padding: 0;
+    max-height: 320px;
+    overflow-y: auto;
+  }
+
+  #pakeSwitcherList li {
+    padding: 6px 8px;
+    border-radius: 4px;
+    color: #11182B;
+    cursor: pointer;
+    white-space: nowrap;
+    overflow: hidden;
+    text-overflow: ellipsis;
+  }
+
+  #pakeSwitcherList li[aria-selected="true"] {
+    background: #11182B;
+    color: #fff;
+  }
+
+  @media (prefers-color-scheme: dark) {
+    #pakeSwitcher .pake-modal-content {
+      background-color: #1f2937;
+    }
+
+    #pakeSwitcherInput {
+      background: #111827;
+      color: #f9fafb;
+      border-color: #4b5563;
+    }
+
+    #pakeSwitcherList li {
+      color: #f9fafb;
+    }
+
+    #pakeSwitcherList li[aria-selected="true"] {
+      background: #f9fafb;
+      color: #111827;
+    }
+  }
+
+  @media (prefers-reduced-motion: reduce) {
+    .pake-modal * {
+      transition: none !important;
+      animation: none !important;
+    }
+  }
+
+  .pake-a11y .pake-modal-content {
+    border: 2px solid #000;
+  }
+
+  .pake-a11y .pake-modal-content button:focus,
+  .pake-a11y .pake-modal-content input:focus,
+  .pake-toast:focus {
+    outline: 3px solid #ffbf47;
+    outline-offset: 2px;
+  }
   `;
 
   const modalDiv = document.createElement('div');
@@ -95,6 +167,12 @@ document.addEventListener('DOMContentLoaded', () => {
   const urlInput = document.getElementById('pakeUrlInput');
   const urlSubmit = document.getElementById('pakeUrlSubmit');
   const urlClose = document.getElementById('pakeUrlClose');
+  const urlModalTrap = createFocusTrap(urlModal, document);
+
+  function closeUrlModal() {
+    urlModal.style.display = 'none';
+    urlModalTrap.deactivate();
+  }
 
   urlSubmit.onclick = function () {
     const url = urlInput.value;
@@ -103,9 +181,7 @@ document.addEventListener('DOMContentLoaded', () => {
     }
   };
 
-  urlClose.onclick = function () {
-    urlModal.style.display = 'none';
-  };
+  urlClose.onclick = closeUrlModal;
 
   urlInput.addEventListener('keydown', function (event) {
     if (event.key === 'Enter') {
@@ -118,22 +194,226 @@ document.addEventListener('DOMContentLoaded', () => {
 
   document.addEventListener('keydown', function (event) {
     if (event.key === 'Escape' && urlModal.style.display === 'block') {
-      urlModal.style.display = 'none';
+      closeUrlModal();
     }
   });
 
   window.showUrlModal = function () {
     urlModal.style.display = 'block';
+    urlModalTrap.activate();
     urlInput.focus();
   };
 
+  // Quick switcher over the configured quick links and the visited pages.
+  const switcher = document.createElement('div');
+  switcher.id = 'pakeSwitcher';
+  switcher.className = 'pake-modal';
+  switcher.setAttribute('role', 'dialog');
+  switcher.setAttribute('aria-modal', 'true');
+  switcher.setAttribute('aria-label', 'Quick switcher');
+  switcher.innerHTML = `
+    <div class="pake-modal-container">
+      <div class="pake-modal-content">
+        <input type="text" id="pakeSwitcherInput" role="combobox" aria-controls="pakeSwitcherList" aria-expanded="true" placeholder="Jump to..." />
+        <ul id="pakeSwitcherList" role="listbox"></ul>
+      </div>
+    </div>
+  `;
+  document.body.appendChild(switcher);
+
+  const switcherInput = document.getElementById('pakeSwitcherInput');
+  const switcherList = document.getElementById('pakeSwitcherList');
+  const switcherTrap = createFocusTrap(switcher, document);
+  let switcherItems = [];
+  let switcherSelected = 0;
+
+  function renderSwitcher() {
+    switcherList.innerHTML = '';
+    switcherItems.forEach((item, index) => {
+      const option = document.createElement('li');
+      option.id = `pakeSwitcherItem${index}`;
+      option.setAttribute('role', 'option');
+      option.setAttribute('aria-selected', index === switcherSelected);
+      option.textContent = item.title || item.url;
+      option.title = item.url;
+      option.onclick = () => openSwitcherItem(item);
+      switcherList.appendChild(option);
+    });
+    switcherInput.setAttribute(
+      'aria-activedescendant',
+      switcherItems.length ? `pakeSwitcherItem${switcherSelected}` : '',
+    );
+  }
+
+  function refreshSwitcher() {
+    invoke('get_switcher_items', { query: switcherInput.value }).then(
+      (items) => {
+        switcherItems = items;
+        switcherSelected = 0;
+        renderSwitcher();
+      },
+    );
+  }
+
+  function closeSwitcher() {
+    switcher.style.display = 'none';
+    switcherTrap.deactivate();
+  }
+
+  // Other hosts open in the browser, like any other external link.
+  function openSwitcherItem(item) {
+    closeSwitcher();
+    const url = new URL(item.url, window.location.href);
+    if (url.host === window.location.host) {
+      window.location.href = url.href;
+    } else {
+      window.__TAURI__.shell.open(url.href);
+    }
+  }
+
+  switcherInput.addEventListener('input', refreshSwitcher);
+  switcherInput.addEventListener('keydown', function (event) {
+    if (event.key === 'ArrowDown' || event.key === 'ArrowUp') {
+      event.preventDefault();
+      const step = event.key === 'ArrowDown' ? 1 : -1;
+      const count = switcherItems.length || 1;
+      switcherSelected = (switcherSelected + step + count) % count;
+      renderSwitcher();
+    } else if (event.key === 'Enter' && switcherItems[switcherSelected]) {
+      openSwitcherItem(switcherItems[switcherSelected]);
+    } else if (event.key === 'Escape') {
+      closeSwitcher();
+    }
+  });
+
+  window.toggleSwitcher = function () {
+    if (switcher.style.display === 'block') {
+      closeSwitcher();
+      return;
+    }
+    switcher.style.display = 'block';
+    switcherTrap.activate();
+    switcherInput.value = '';
+    switcherInput.focus();
+    refreshSwitcher();
+  };
+
+  // Sites that use CmdOrCtrl+K themselves prevent the default, and keep the shortcut.
+  // Their listeners can sit anywhere, so the check waits until the event is fully dispatched.
+  window.addEventListener('keydown', function (event) {
+    if (!matchesShortcut(event, 'CmdOrCtrl+K')) {
+      return;
+    }
+    setTimeout(() => {
+      if (!event.defaultPrevented) {
+        window.toggleSwitcher();
+      }
+    });
+  });
+
+  // A quiet marker so demo staff can tell readonly mode is on.
+  if (window.pakeConfig.readonly_mode) {
+    const readonlyBadge = document.createElement('div');
+    readonlyBadge.textContent = 'Read-only';
+    readonlyBadge.setAttribute('aria-label', 'Read-only mode is active');
+    Object.assign(readonlyBadge.style, {
+      position: 'fixed',
+      bottom: '6px',
+      left: '8px',
+      zIndex: '999999',
+      padding: '2px 6px',
+      fontSize: '10px',
+      color: 'rgba(255, 255, 255, 0.9)',
+      background: 'rgba(0, 0, 0, 0.35)',
+      borderRadius: '4px',
+      pointerEvents: 'none',
+    });
+    document.body.appendChild(readonlyBadge);
+  }
+
+  // Omnibox searching the wrapped site.
+  const omnibox = window.pakeConfig.omnibox;
+  if (omnibox && omnibox.enabled) {
+    document.addEventListener('keydown', function (event) {
+      if (matchesShortcut(event, omnibox.shortcut)) {
+        event.preventDefault();
+        showOmnibox(omnibox.search_url);
+      }
+    });
+  }
+
+  // A strict style-src CSP blocks the injected style element, fall back to CSSOM styles.
+  const uiDegraded = !isStyleApplied(urlModal, window.getComputedStyle);
+  if (uiDegraded) {
+    applyModalInlineStyles(urlModal);
+    invoke('ui_degraded', { reason: 'style-src' }).catch(() => {});
+  }
+
   // Toast
-  function pakeToast(msg) {
+  function pakeToast(msg, actions = []) {
     const m = document.createElement('div');
-    m.innerHTML = msg;
-    m.style.cssText =
-      'max-width:60%;min-width: 80px;padding:0 12px;height: 32px;color: rgb(255, 255, 255);line-height: 32px;text-align: center;border-radius: 8px;position: fixed; bottom:24px;right: 28px;z-index: 999999;background: rgba(0, 0, 0,.8);font-size: 13px;';
+    m.textContent = msg;
+    for (const action of actions) {
+      const button = document.createElement('button');
+      button.textContent = action.label;
+      Object.assign(button.style, {
+        marginLeft: '10px',
+        padding: '0',
+        border: 'none',
+        background: 'none',
+        color: 'rgb(100, 180, 255)',
+        font: 'inherit',
+        cursor: 'pointer',
+      });
+      button.addEventListener('click', action.onClick);
+      m.appendChild(button);
+    }
+    m.className = 'pake-toast';
+    m.setAttribute('role', 'status');
+    m.setAttribute('aria-live', 'polite');
+    m.tabIndex = -1;
+    Object.assign(m.style, {
+      maxWidth: '60%',
+      minWidth: '80px',
+      padding: '0 12px',
+      height: '32px',
+      color: 'rgb(255, 255, 255)',
+      lineHeight: '32px',
+      textAlign: 'center',
+      borderRadius: '8px',
+      position: 'fixed',
+      bottom: '24px',
+      right: '28px',
+      zIndex: '999999',
+      background: 'rgba(0, 0, 0,.8)',
+      fontSize: '13px',
+    });
     document.body.appendChild(m);
+
+    // Last resort when even CSSOM styles don't apply.
+    if (uiDegraded && !isStyleApplied(m, window.getComputedStyle)) {
+      document.body.removeChild(m);
+      window.__TAURI__.notification.sendNotification({
+        title: document.title,
+        body: m.innerText,
+      });
+      return;
+    }
+
+    // In accessibility mode toasts stay until they are dismissed with Enter or Escape.
+    if (isA11yMode()) {
+      m.style.background = 'rgb(0, 0, 0)';
+      m.style.border = '2px solid rgb(255, 255, 255)';
+      m.addEventListener('keydown', function (event) {
+        if (event.key === 'Enter' || event.key === 'Escape') {
+          document.body.removeChild(m);
+        }
+      });
+      m.addEventListener('click', function () {
+        document.body.removeChild(m);
+      });
+      return;
+    }
     setTimeout(function () {
       const d = 0.5;
       m.style.transition =
@@ -142,11 +422,291 @@ document.addEventListener('DOMContentLoaded', () => {
       setTimeout(function () {
         document.body.removeChild(m);
       }, d * 1000);
-    }, 3000);
+    }, actions.length ? 6000 : 3000);
   }
 
   window.pakeToast = pakeToast;
 
+  window.pakeDownloadToast = function (msg, path) {
+    pakeToast(msg, [
+      {
+        label: 'Open',
+        onClick: () => invoke('open_downloaded_file', { path }),
+      },
+      {
+        label: 'Show in folder',
+        onClick: () => invoke('reveal_in_folder', { path }),
+      },
+    ]);
+  };
+
+  // F6 moves the focus to the newest toast, so it can be read and dismissed.
+  document.addEventListener('keydown', function (event) {
+    if (event.key === 'F6') {
+      const toasts = document.querySelectorAll('.pake-toast');
+      if (toasts.length) {
+        event.preventDefault();
+        toasts[toasts.length - 1].focus();
+      }
+    }
+  });
+
+  window.setA11yMode = function (enabled) {
+    window.localStorage.setItem('pakeA11yMode', enabled ? '1' : '0');
+    document.documentElement.classList.toggle('pake-a11y', enabled);
+  };
+  document.documentElement.classList.toggle('pake-a11y', isA11yMode());
+
+  // A total of 0 means the size is unknown, so the bar only shows activity.
+  function pakeDownloadProgress(received, total, done) {
+    let bar = document.getElementById('pakeDownloadProgress');
+    if (done) {
+      bar && document.body.removeChild(bar);
+      return;
+    }
+    if (!bar) {
+      bar = document.createElement('div');
+      bar.id = 'pakeDownloadProgress';
+      Object.assign(bar.style, {
+        position: 'fixed',
+        top: '0',
+        left: '0',
+        height: '3px',
+        zIndex: '999999',
+        background: 'rgb(0, 122, 255)',
+        transition: 'width 0.1s linear',
+      });
+      document.body.appendChild(bar);
+    }
+    if (total > 0) {
+      bar.style.width = `${Math.min(100, (received / total) * 100)}%`;
+      bar.style.opacity = '1';
+    } else {
+      bar.style.width = '100%';
+      bar.style.opacity = bar.style.opacity === '0.4' ? '1' : '0.4';
+    }
+  }
+
+  window.pakeDownloadProgress = pakeDownloadProgress;
+  // Pages with their own download UI can offer a cancel button.
+  window.pakeCancelDownload = () => invoke('cancel_download');
+
+  // Recent downloads, newest first. Cmd+J or Ctrl+J toggles the panel.
+  let downloadItems = [];
+  let downloadsPanel = null;
+  const downloadStatusLabels = {
+    done: 'Done',
+    failed: 'Failed',
+    cancelled: 'Cancelled',
+  };
+
+  function formatBytes(bytes) {
+    const units = ['B', 'KB', 'MB', 'GB'];
+    let unit = 0;
+    while (bytes >= 1024 && unit < units.length - 1) {
+      bytes /= 1024;
+      unit++;
+    }
+    return `${bytes.toFixed(unit ? 1 : 0)} ${units[unit]}`;
+  }
+
+  function describeDownload(item) {
+    if (item.status !== 'active') {
+      return downloadStatusLabels[item.status];
+    }
+    let text =
+      item.total > 0
+        ? `${formatBytes(item.received)} of ${formatBytes(item.total)}`
+        : formatBytes(item.received);
+    if (item.speed > 0) {
+      text += `, ${formatBytes(item.speed)}/s`;
+    }
+    if (item.eta !== null) {
+      text += `, ${item.eta}s left`;
+    }
+    return text;
+  }
+
+  function createDownloadButton(label, onClick) {
+    const button = document.createElement('button');
+    button.textContent = label;
+    Object.assign(button.style, {
+      marginRight: '10px',
+      padding: '0',
+      border: 'none',
+      background: 'none',
+      color: 'rgb(100, 180, 255)',
+      font: 'inherit',
+      cursor: 'pointer',
+    });
+    button.addEventListener('click', onClick);
+    return button;
+  }
+
+  function renderDownloads() {
+    if (!downloadsPanel) {
+      return;
+    }
+    downloadsPanel.replaceChildren();
+    if (!downloadItems.length) {
+      downloadsPanel.textContent = 'No downloads yet';
+      return;
+    }
+    for (const item of [...downloadItems].reverse()) {
+      const row = document.createElement('div');
+      row.style.marginBottom = '10px';
+
+      const name = document.createElement('div');
+      name.textContent = item.filename;
+      Object.assign(name.style, {
+        overflow: 'hidden',
+        textOverflow: 'ellipsis',
+        whiteSpace: 'nowrap',
+      });
+      const status = document.createElement('div');
+      status.textContent = describeDownload(item);
+      status.style.opacity = '0.7';
+      row.append(name, status);
+
+      // Without a known size the bar only pulses.
+      if (item.status === 'active') {
+        const bar = document.createElement('div');
+        Object.assign(bar.style, {
+          height: '3px',
+          margin: '4px 0',
+          background: 'rgb(0, 122, 255)',
+          width:
+            item.total > 0
+              ? `${Math.min(100, (item.received / item.total) * 100)}%`
+              : '100%',
+        });
+        if (item.total === 0) {
+          bar.animate([{ opacity: 1 }, { opacity: 0.3 }, { opacity: 1 }], {
+            duration: 1200,
+            iterations: Infinity,
+          });
+        }
+        row.append(
+          bar,
+          createDownloadButton('Cancel', () =>
+            invoke('cancel_download', { id: item.id }),
+          ),
+        );
+      } else if (item.status === 'done') {
+        row.append(
+          createDownloadButton('Open', () =>
+            invoke('open_downloaded_file', { path: item.path }),
+          ),
+          createDownloadButton('Show in folder', () =>
+            invoke('reveal_in_folder', { path: item.path }),
+          ),
+        );
+      }
+      downloadsPanel.appendChild(row);
+    }
+  }
+
+  function pakeToggleDownloads() {
+    if (downloadsPanel) {
+      document.body.removeChild(downloadsPanel);
+      downloadsPanel = null;
+      return;
+    }
+    downloadsPanel = document.createElement('div');
+    downloadsPanel.setAttribute('role', 'region');
+    downloadsPanel.setAttribute('aria-label', 'Downloads');
+    Object.assign(downloadsPanel.style, {
+      position: 'fixed',
+      top: '24px',
+      right: '28px',
+      width: '300px',
+      maxHeight: '60%',
+      overflowY: 'auto',
+      padding: '12px',
+      borderRadius: '8px',
+      zIndex: '999999',
+      color: 'rgb(255, 255, 255)',
+      background: 'rgba(0, 0, 0, .85)',
+      fontSize: '13px',
+    });
+    document.body.appendChild(downloadsPanel);
+    renderDownloads();
+    invoke('get_downloads')
+      .then((items) => {
+        downloadItems = items;
+        renderDownloads();
+      })
+      .catch(() => {});
+  }
+
+  window.pakeToggleDownloads = pakeToggleDownloads;
+  window.__TAURI__.event.listen('downloads_changed', (event) => {
+    downloadItems = event.payload;
+    renderDownloads();
+  });
+  document.addEventListener('keydown', (event) => {
+    if (
+      (event.metaKey || event.ctrlKey) &&
+      !event.shiftKey &&
+      !event.altKey &&
+      event.key.toLowerCase() === 'j'
+    ) {
+      event.preventDefault();
+      pakeToggleDownloads();
+    }
+  });
+
+  // Page size used when printing to PDF.
+  const pdfPageSize = window.pakeConfig && window.pakeConfig.pdf_page_size;
+  if (pdfPageSize) {
+    const pageStyleElement = document.createElement('style');
+    pageStyleElement.innerText = `@page { size: ${pdfPageSize}; }`;
+    document.head.appendChild(pageStyleElement);
+  }
+
+  // Frosted glass behind transparent page content.
+  const backdropFilter = window.pakeConfig && window.pakeConfig.backdrop_filter;
+  if (backdropFilter) {
+    Object.assign(document.body.style, {
+      webkitBackdropFilter: backdropFilter,
+      backdropFilter,
+    });
+  }
+
+  // keyboard_only shows outlines after a key press and hides them after a click,
+  // for pages that don't style :focus-visible.
+  const focusRing = window.pakeConfig && window.pakeConfig.focus_ring;
+  const focusRingStyles = {
+    always: '* { outline: revert !important; }',
+    never: '* { outline: none !important; }',
+    keyboard_only: `
+      html.pake-keyboard-focus *:focus { outline: revert !important; }
+      html:not(.pake-keyboard-focus) *:focus { outline: none !important; }
+    `,
+  };
+  if (focusRingStyles[focusRing]) {
+    const focusRingStyle = document.createElement('style');
+    focusRingStyle.innerText = focusRingStyles[focusRing];
+    document.head.appendChild(focusRingStyle);
+  }
+  if (focusRing === 'keyboard_only') {
+    const root = document.documentElement;
+    document.addEventListener(
+      'keydown',
+      (event) => {
+        if (!event.metaKey && !event.ctrlKey && !event.altKey) {
+          root.classList.add('pake-keyboard-focus');
+        }
+      },
+      true,
+    );
+    document.addEventListener(
+      'pointerdown',
+      () => root.classList.remove('pake-keyboard-focus'),
+      true,
+    );
+  }
+
   // chatgpt supports unlimited times of GPT4-Mobile
   if (window.location.hostname === 'chat.openai.com') {
     const originFetch = fetch;
@@ -179,3 +739,141 @@ document.addEventListener('DOMContentLoaded', () => {
     };
   }
 });
+
+// Understands accelerators like CmdOrCtrl+Shift+O.
+function matchesShortcut(event, shortcut) {
+  const isMac = window.pakeConfig.platform === 'macos';
+  const parts = shortcut.toLowerCase().split('+');
+  const key = parts.pop();
+  const wants = (modifier) => parts.includes(modifier);
+  const cmdOrCtrl = wants('cmdorctrl') || wants('commandorcontrol');
+  return (
+    event.key.toLowerCase() === key &&
+    event.shiftKey === wants('shift') &&
+    event.altKey === (wants('alt') || wants('option')) &&
+    event.metaKey === (wants('cmd') || wants('command') || (cmdOrCtrl && isMac)) &&
+    event.ctrlKey === (wants('ctrl') || wants('control') || (cmdOrCtrl && !isMac))
+  );
+}
+
+function showOmnibox(searchUrl) {
+  if (document.getElementById('pakeOmnibox')) {
+    document.getElementById('pakeOmnibox').focus();
+    return;
+  }
+  const isMac = window.pakeConfig.platform === 'macos';
+  const input = document.createElement('input');
+  input.id = 'pakeOmnibox';
+  input.type = 'search';
+  input.placeholder = 'Search';
+  input.setAttribute('aria-label', 'Search');
+  Object.assign(input.style, {
+    position: 'fixed',
+    top: '20%',
+    left: '50%',
+    transform: 'translateX(-50%)',
+    width: '480px',
+    maxWidth: '80%',
+    padding: '10px 14px',
+    zIndex: '999999',
+    fontSize: '16px',
+    color: '#11182B',
+    background: isMac ? 'rgba(255, 255, 255, 0.85)' : '#fff',
+    backdropFilter: isMac ? 'blur(20px)' : 'none',
+    border: isMac ? '1px solid rgba(0, 0, 0, 0.1)' : '1px solid #8a8a8a',
+    borderRadius: isMac ? '10px' : '4px',
+    boxShadow: '0 8px 24px rgba(0, 0, 0, 0.2)',
+    fontFamily: isMac ? '-apple-system, sans-serif' : 'Segoe UI, sans-serif',
+    outline: 'none',
+  });
+  input.addEventListener('keydown', (event) => {
+    if (event.key === 'Enter' && input.value.trim()) {
+      window.location.href = searchUrl.replace(
+        '{query}',
+        encodeURIComponent(input.value.trim()),
+      );
+    }
+    if (event.key === 'Enter' || event.key === 'Escape') {
+      input.remove();
+    }
+  });
+  input.addEventListener('blur', () => input.remove());
+  document.body.appendChild(input);
+  input.focus();
+}
+
+function isA11yMode() {
+  return window.localStorage.getItem('pakeA11yMode') === '1';
+}
+
+// Keeps Tab and Shift+Tab inside the container, and gives the focus back when released.
+function createFocusTrap(container, doc) {
+  const focusableSelector =
+    'a[href], button:not([disabled]), input:not([disabled]), select, textarea, [tabindex]:not([tabindex="-1"])';
+  let previousFocus = null;
+
+  function onKeydown(event) {
+    if (event.key !== 'Tab') {
+      return;
+    }
+    const focusable = Array.from(container.querySelectorAll(focusableSelector));
+    if (!focusable.length) {
+      return;
+    }
+    const first = focusable[0];
+    const last = focusable[focusable.length - 1];
+    if (event.shiftKey && doc.activeElement === first) {
+      event.preventDefault();
+      last.focus();
+    } else if (!event.shiftKey && doc.activeElement === last) {
+      event.preventDefault();
+      first.focus();
+    }
+  }
+
+  return {
+    activate() {
+      previousFocus = doc.activeElement;
+      container.addEventListener('keydown', onKeydown);
+    },
+    deactivate() {
+      container.removeEventListener('keydown', onKeydown);
+      if (previousFocus && previousFocus.focus) {
+        previousFocus.focus();
+      }
+      previousFocus = null;
+    },
+  };
+}
+
+// The injected styles make every modal fixed, anything else means they were blocked.
+function isStyleApplied(element, getComputedStyle) {
+  return getComputedStyle(element).position === 'fixed';
+}
+
+function applyModalInlineStyles(modal) {
+  Object.assign(modal.style, {
+    position: 'fixed',
+    zIndex: '1000',
+    left: '0',
+    top: '0',
+    width: '100%',
+    height: '100%',
+    backgroundColor: 'rgba(0, 0, 0, 0.4)',
+  });
+  Object.assign(modal.querySelector('.pake-modal-container').style, {
+    display: 'flex',
+    alignItems: 'center',
+    justifyContent: 'center',
+    width: '100%',
+    height: '100%',
+  });
+  Object.assign(modal.querySelector('.pake-modal-content').style, {
+    backgroundColor: '#fff',
+    padding: '20px',
+    borderRadius: '10px',
+    width: '80%',
+    maxWidth: '400px',
+    fontSize: '14px',
+  });
+}
@@ -8,16 +8,126 @@ const shortcuts = {
   '[': () => window.history.back(),
   ']': () => window.history.forward(),
   r: () => window.location.reload(),
+  R: () => pakeHardReload(),
   '-': () => zoomOut(),
   '=': () => zoomIn(),
   '+': () => zoomIn(),
   0: () => setZoom('100%'),
 };
 
-function setZoom(zoom) {
+// Paint the page the way it last looked, so there is no flash before its styles load.
+function applyAppearanceSnapshot() {
+  const appearance = window.pakeState && window.pakeState.appearance;
+  const root = document.documentElement;
+  if (!appearance || !root) {
+    return false;
+  }
+  root.style.backgroundColor = appearance.background;
+  root.style.direction = appearance.direction;
+  root.style.colorScheme = appearance.color_scheme;
+  return true;
+}
+
+const appearanceSnapshotApplied = applyAppearanceSnapshot();
+
+// A spinner over the page until it has loaded, so a slow start doesn't look broken.
+// Pages can take it down early with window.pakeHideSplash().
+const SPLASH_TIMEOUT = 30000;
+
+function showSplash() {
+  if (!window.pakeConfig.splash || window.self !== window.top) {
+    return;
+  }
+  const appearance = window.pakeState && window.pakeState.appearance;
+  const splash = document.createElement('div');
+  splash.id = 'pakeSplash';
+  Object.assign(splash.style, {
+    position: 'fixed',
+    inset: '0',
+    zIndex: '2147483647',
+    display: 'flex',
+    alignItems: 'center',
+    justifyContent: 'center',
+    background: appearance ? appearance.background : 'rgb(255, 255, 255)',
+  });
+  const spinner = document.createElement('div');
+  Object.assign(spinner.style, {
+    width: '28px',
+    height: '28px',
+    border: '3px solid rgba(128, 128, 128, .3)',
+    borderTopColor: 'rgb(128, 128, 128)',
+    borderRadius: '50%',
+  });
+  spinner.animate(
+    [{ transform: 'rotate(0deg)' }, { transform: 'rotate(360deg)' }],
+    { duration: 800, iterations: Infinity },
+  );
+  splash.appendChild(spinner);
+
+  // Chromium runs this before the document has an element to attach to.
+  if (document.documentElement) {
+    document.documentElement.appendChild(splash);
+  } else {
+    new MutationObserver((_, observer) => {
+      if (document.documentElement) {
+        observer.disconnect();
+        document.documentElement.appendChild(splash);
+      }
+    }).observe(document, { childList: true });
+  }
+  setTimeout(window.pakeHideSplash, SPLASH_TIMEOUT);
+}
+
+window.pakeHideSplash = () => {
+  const splash = document.getElementById('pakeSplash');
+  splash && splash.remove();
+};
+window.addEventListener('load', window.pakeHideSplash);
+showSplash();
+
+function captureAppearance() {
+  const isTransparent = (color) =>
+    color === 'transparent' || color === 'rgba(0, 0, 0, 0)';
+  let background = getComputedStyle(document.body).backgroundColor;
+  if (isTransparent(background)) {
+    background = getComputedStyle(document.documentElement).backgroundColor;
+  }
+  return {
+    background: isTransparent(background) ? 'rgb(255, 255, 255)' : background,
+    direction: getComputedStyle(document.documentElement).direction,
+    color_scheme: window.matchMedia('(prefers-color-scheme: dark)').matches
+      ? 'dark'
+      : 'light',
+    icon_hash: getIconHash(),
+  };
+}
+
+// Sites usually change their favicon url when they ship a new version.
+function getIconHash() {
+  const icon = document.querySelector('link[rel~="icon"]');
+  const href = icon ? icon.href : '';
+  let hash = 0;
+  for (let i = 0; i < href.length; i++) {
+    hash = (hash * 31 + href.charCodeAt(i)) | 0;
+  }
+  return hash.toString(16);
+}
+
+function saveAppearance() {
+  return invoke('save_appearance', { params: captureAppearance() }).catch(
+    () => {},
+  );
+}
+
+function applyZoom(zoom) {
   const html = document.getElementsByTagName('html')[0];
   html.style.zoom = zoom;
+}
+
+function setZoom(zoom) {
+  applyZoom(zoom);
   window.localStorage.setItem('htmlZoom', zoom);
+  invoke('save_zoom', { zoom: parseInt(zoom) / 100 }).catch(() => {});
 }
 
 function zoomCommon(zoomChange) {
@@ -25,12 +135,67 @@ function zoomCommon(zoomChange) {
   setZoom(zoomChange(currentZoom));
 }
 
+// Percentages, set_zoom clamps to the same range.
+const MIN_ZOOM = 25;
+const MAX_ZOOM = 500;
+
 function zoomIn() {
-  zoomCommon((currentZoom) => `${Math.min(parseInt(currentZoom) + 10, 200)}%`);
+  zoomCommon(
+    (currentZoom) => `${Math.min(parseInt(currentZoom) + 10, MAX_ZOOM)}%`,
+  );
 }
 
 function zoomOut() {
-  zoomCommon((currentZoom) => `${Math.max(parseInt(currentZoom) - 10, 30)}%`);
+  zoomCommon(
+    (currentZoom) => `${Math.max(parseInt(currentZoom) - 10, MIN_ZOOM)}%`,
+  );
+}
+
+// Force a fixed scale for pages designed around a 1:1 pixel ratio.
+function getForceScale() {
+  const scale = window.localStorage.getItem('pakeForceScale');
+  if (scale) {
+    return parseFloat(scale);
+  }
+  return window.pakeConfig && window.pakeConfig.windows[0].force_scale;
+}
+
+function applyForceScale(scale) {
+  Object.defineProperty(window, 'devicePixelRatio', {
+    get: () => scale,
+    configurable: true,
+  });
+  if (document.body) {
+    document.body.style.transform = `scale(${scale})`;
+    document.body.style.transformOrigin = '0 0';
+    document.body.style.width = `${100 / scale}%`;
+  }
+}
+
+function setForceScale(scale) {
+  window.localStorage.setItem('pakeForceScale', scale);
+  applyForceScale(scale);
+}
+
+if (getForceScale()) {
+  applyForceScale(getForceScale());
+}
+
+// Webviews ignore location.reload(true), so the page and its same-origin scripts and styles are
+// fetched past the cache first. Other resources may still come from the cache.
+function pakeHardReload() {
+  const urls = performance
+    .getEntriesByType('resource')
+    .filter(
+      (entry) =>
+        ['script', 'link', 'css'].includes(entry.initiatorType) &&
+        new URL(entry.name).origin === window.location.origin,
+    )
+    .map((entry) => entry.name);
+  urls.push(window.location.href);
+  Promise.allSettled(
+    urls.map((url) => fetch(url, { cache: 'reload', credentials: 'include' })),
+  ).finally(() => window.location.reload());
 }
 
 function handleShortcut(event) {
@@ -59,7 +224,8 @@ function transformCallback(callback = () => {}, once = false) {
   return identifier;
 }
 
-async function invoke(cmd, args) {
+// Only calls made for the user pass shakeOnError, background ones fail quietly.
+async function invoke(cmd, args, { shakeOnError = false } = {}) {
   return new Promise((resolve, reject) => {
     if (!window.__TAURI_POST_MESSAGE__)
       reject('__TAURI_POST_MESSAGE__ does not exist~');
@@ -68,6 +234,9 @@ async function invoke(cmd, args) {
       Reflect.deleteProperty(window, `_${error}`);
     }, true);
     const error = transformCallback((e) => {
+      if (shakeOnError && window.pakeConfig.shake_on_error) {
+        invoke('shake').catch(() => {});
+      }
       reject(e);
       Reflect.deleteProperty(window, `_${callback}`);
     }, true);
@@ -80,6 +249,195 @@ async function invoke(cmd, args) {
   });
 }
 
+// Relay page errors to the Rust log, installed before any page script runs.
+function captureConsole() {
+  const level = window.pakeConfig && window.pakeConfig.capture_console;
+  if (!level || level === 'off') {
+    return;
+  }
+
+  const report = (level, message, source, line, stack) => {
+    invoke('console_error', {
+      params: {
+        level,
+        // Cross-origin scripts only report "Script error." without details.
+        message: String(message || 'Script error.'),
+        source: source || null,
+        line: line || null,
+        stack: stack ? String(stack).slice(0, 2000) : null,
+      },
+    }).catch(() => {});
+  };
+
+  window.addEventListener('error', (event) => {
+    const stack = event.error && event.error.stack;
+    report('error', event.message, event.filename, event.lineno, stack);
+  });
+
+  window.addEventListener('unhandledrejection', (event) => {
+    const reason = event.reason;
+    const message = reason && reason.message ? reason.message : reason;
+    report('error', message, null, null, reason && reason.stack);
+  });
+
+  if (level === 'warnings') {
+    const originalWarn = console.warn;
+    console.warn = (...args) => {
+      report('warn', args.join(' '));
+      originalWarn.apply(console, args);
+    };
+  }
+}
+
+captureConsole();
+
+// The flags in the init script are the ones from when the window was built,
+// so each page load asks for the current ones and announces them with pake-flags-changed.
+function refreshFeatureFlags() {
+  invoke('get_feature_flags')
+    .then((flags) => {
+      window.PAKE_FLAGS = Object.assign(window.PAKE_FLAGS || {}, flags);
+      window.dispatchEvent(
+        new CustomEvent('pake-flags-changed', { detail: window.PAKE_FLAGS }),
+      );
+    })
+    .catch(() => {});
+}
+
+refreshFeatureFlags();
+
+// The webview has no permission prompt for notifications, so pages get native ones instead.
+// Each origin is asked once with a native prompt. Clicking a notification doesn't reach the page.
+function shimNotification() {
+  let permission = 'default';
+  invoke('get_notification_permission')
+    .then((result) => (permission = result))
+    .catch(() => {});
+
+  class PakeNotification extends EventTarget {
+    constructor(title, options = {}) {
+      super();
+      this.title = title;
+      this.body = options.body || '';
+      this.icon = options.icon || '';
+      this.onclick = null;
+      this.onclose = null;
+      invoke('notify', {
+        params: { title: String(title), body: String(this.body) },
+      }).catch(() => {});
+    }
+
+    close() {}
+
+    static get permission() {
+      return permission;
+    }
+
+    static requestPermission(callback) {
+      return invoke('request_notification_permission')
+        .catch(() => 'denied')
+        .then((result) => {
+          permission = result;
+          callback && callback(result);
+          return result;
+        });
+    }
+  }
+
+  window.Notification = PakeNotification;
+}
+
+shimNotification();
+
+// Persistent storage is asked for with a native prompt, estimate() is left to the webview.
+function shimStoragePersist() {
+  if (!navigator.storage) {
+    return;
+  }
+  navigator.storage.persist = () =>
+    invoke('request_persistent_storage').catch(() => false);
+  navigator.storage.persisted = () =>
+    invoke('is_storage_persisted').catch(() => false);
+}
+
+shimStoragePersist();
+
+// Kiosk demos can look around but not submit, upload or copy anything.
+function isReadonlyBlocked(url) {
+  const config = window.pakeConfig;
+  if (!config || !config.readonly_mode) {
+    return false;
+  }
+  return !config.readonly_allowlist.some((pattern) =>
+    new RegExp(pattern).test(url),
+  );
+}
+
+function showReadonlyToast() {
+  window.pakeToast && window.pakeToast('This app is in read-only mode');
+}
+
+function enforceReadonlyMode() {
+  if (!window.pakeConfig || !window.pakeConfig.readonly_mode) {
+    return;
+  }
+
+  document.addEventListener(
+    'submit',
+    (event) => {
+      if (isReadonlyBlocked(event.target.action || window.location.href)) {
+        event.preventDefault();
+        event.stopImmediatePropagation();
+        showReadonlyToast();
+      }
+    },
+    true,
+  );
+
+  const originalSubmit = HTMLFormElement.prototype.submit;
+  HTMLFormElement.prototype.submit = function () {
+    if (isReadonlyBlocked(this.action || window.location.href)) {
+      showReadonlyToast();
+      return;
+    }
+    return originalSubmit.call(this);
+  };
+
+  document.addEventListener(
+    'click',
+    (event) => {
+      const input = event.target.closest && event.target.closest('input');
+      if (input && input.type === 'file') {
+        event.preventDefault();
+        showReadonlyToast();
+      }
+    },
+    true,
+  );
+
+  ['copy', 'cut'].forEach((type) =>
+    document.addEventListener(
+      type,
+      (event) => {
+        event.preventDefault();
+        showReadonlyToast();
+      },
+      true,
+    ),
+  );
+
+  if (navigator.clipboard) {
+    const rejectWrite = () => {
+      showReadonlyToast();
+      return Promise.reject(new DOMException('readonly', 'NotAllowedError'));
+    };
+    navigator.clipboard.writeText = rejectWrite;
+    navigator.clipboard.write = rejectWrite;
+  }
+}
+
+enforceReadonlyMode();
+
 // Judgment of file download.
 function isDownloadLink(url) {
     const fileExtensions = [
@@ -97,6 +455,127 @@ function externalDownLoadLink() {
   return ['quickref.me'].indexOf(location.hostname) > -1;
 }
 
+// The configured site and its subdomains, with or without www.
+// is_same_host in util.rs follows the same rule, keep them in step.
+function isAppHost(url) {
+  const appHost = new URL(window.pakeConfig.windows[0].url).hostname.replace(
+    /^www\./,
+    '',
+  );
+  return url.hostname === appHost || url.hostname.endsWith(`.${appHost}`);
+}
+
+// The app's own site stays in the app, as do urls matching internal_urls.
+function isInternalUrl(url) {
+  return (
+    isAppHost(url) ||
+    (window.pakeConfig.internal_urls || []).some((pattern) =>
+      new RegExp(pattern).test(url.href),
+    )
+  );
+}
+
+// Runs the steps of a configured macro one after another, pages can call it as window.pakeRunMacro.
+async function runMacro(name) {
+  const macro = (window.pakeConfig.macros || {})[name];
+  if (!macro) {
+    throw `Unknown macro ${name}`;
+  }
+  for (const step of macro.steps) {
+    try {
+      await invoke(step.command, step.payload, { shakeOnError: true });
+    } catch (e) {
+      console.error(`Macro ${name} failed at ${step.command}:`, e);
+      if (!macro.continue_on_error) {
+        throw e;
+      }
+    }
+  }
+}
+
+window.pakeRunMacro = runMacro;
+
+// Find in page, driven by the find, find_next and find_clear commands.
+let findMatches = [];
+let findIndex = -1;
+
+function pakeFind(query) {
+  pakeFindClear();
+  const needle = query.toLowerCase();
+  if (!needle || !document.body) {
+    return 0;
+  }
+  const walker = document.createTreeWalker(
+    document.body,
+    NodeFilter.SHOW_TEXT,
+    {
+      acceptNode: (node) =>
+        node.parentElement.closest('script, style, noscript, textarea')
+          ? NodeFilter.FILTER_REJECT
+          : NodeFilter.FILTER_ACCEPT,
+    },
+  );
+  const textNodes = [];
+  while (walker.nextNode()) {
+    textNodes.push(walker.currentNode);
+  }
+  for (let node of textNodes) {
+    let index = node.data.toLowerCase().indexOf(needle);
+    while (index !== -1) {
+      const match = node.splitText(index);
+      node = match.splitText(needle.length);
+      const mark = document.createElement('mark');
+      match.parentNode.replaceChild(mark, match);
+      mark.appendChild(match);
+      findMatches.push(mark);
+      index = node.data.toLowerCase().indexOf(needle);
+    }
+  }
+  return pakeFindNext();
+}
+
+// Moves to the next match, starting over at the top after the last one.
+function pakeFindNext() {
+  if (!findMatches.length) {
+    return 0;
+  }
+  if (findIndex >= 0) {
+    findMatches[findIndex].style.backgroundColor = '';
+  }
+  findIndex = (findIndex + 1) % findMatches.length;
+  const current = findMatches[findIndex];
+  current.style.backgroundColor = 'orange';
+  current.scrollIntoView({ block: 'center' });
+  return findMatches.length;
+}
+
+function pakeFindClear() {
+  for (const mark of findMatches) {
+    const parent = mark.parentNode;
+    if (parent) {
+      parent.replaceChild(document.createTextNode(mark.textContent), mark);
+      parent.normalize();
+    }
+  }
+  findMatches = [];
+  findIndex = -1;
+}
+
+// Follows new_window_mode for links and popups that ask for a new window.
+function openNewWindow(url, specs = '') {
+  if (window.pakeConfig.new_window_mode === 'child-window') {
+    const size = (name) => {
+      const match = new RegExp(`${name}=(\\d+)`).exec(specs);
+      return match ? Number(match[1]) : undefined;
+    };
+    invoke('open_popup', {
+      params: { url, width: size('width'), height: size('height') },
+    });
+  } else {
+    window.__TAURI__.shell.open(url);
+  }
+}
+
 // Directly jumping out without hostname address.
 function externalTargetLink() {
   return ['zbook.lol'].indexOf(location.hostname) > -1;
@@ -106,6 +585,23 @@ document.addEventListener('DOMContentLoaded', () => {
   const tauri = window.__TAURI__;
   const appWindow = tauri.window.appWindow;
 
+  // The window title follows the page title, the app limits how often it changes.
+  if (!window.pakeConfig.pin_title) {
+    let lastTitle = null;
+    const syncTitle = () => {
+      if (document.title !== lastTitle) {
+        lastTitle = document.title;
+        invoke('set_title', { title: lastTitle });
+      }
+    };
+    new MutationObserver(syncTitle).observe(document.head, {
+      subtree: true,
+      childList: true,
+      characterData: true,
+    });
+    syncTitle();
+  }
+
   const topDom = document.createElement('div');
   topDom.id = 'pack-top-dom';
   document.body.appendChild(topDom);
@@ -128,27 +624,132 @@ document.addEventListener('DOMContentLoaded', () => {
     });
   });
 
+  const platform = window.pakeConfig.platform;
+  const isMac = platform === 'macos';
+
   document.addEventListener('keyup', (event) => {
-    if (/windows|linux/i.test(navigator.userAgent) && event.ctrlKey) {
+    if (!isMac && event.ctrlKey) {
       handleShortcut(event);
     }
-    if (/macintosh|mac os x/i.test(navigator.userAgent) && event.metaKey) {
+    if (isMac && event.metaKey) {
       handleShortcut(event);
     }
   });
 
+  // Without a menu bar there is no Ctrl+W accelerator on Windows and Linux.
+  if (
+    !isMac &&
+    (window.pakeConfig.hide_menu_bar || !window.pakeConfig.menu[platform])
+  ) {
+    document.addEventListener('keydown', (event) => {
+      if (
+        event.ctrlKey &&
+        !event.shiftKey &&
+        !event.altKey &&
+        event.key.toLowerCase() === 'w'
+      ) {
+        event.preventDefault();
+        invoke('close_shortcut').catch(() => {});
+      }
+    });
+  }
+
+  // Ctrl+scroll zooms like in a browser, one step at a time so a touchpad doesn't race.
+  if (!isMac) {
+    let lastWheelZoom = 0;
+    document.addEventListener(
+      'wheel',
+      (event) => {
+        if (!event.ctrlKey || event.deltaY === 0) {
+          return;
+        }
+        event.preventDefault();
+        if (event.timeStamp - lastWheelZoom < 100) {
+          return;
+        }
+        lastWheelZoom = event.timeStamp;
+        if (event.deltaY < 0) {
+          zoomIn();
+        } else {
+          zoomOut();
+        }
+      },
+      { passive: false },
+    );
+  }
+
   const detectAnchorElementClick = (e) => {
     const anchorElement = e.target.closest('a');
     if (anchorElement && anchorElement.href) {
       const target = anchorElement.target;
       anchorElement.target = '_self';
-      const hrefUrl = new URL(anchorElement.href);
+      const hrefUrl = new URL(applyRewrites(anchorElement.href));
       const absoluteUrl = hrefUrl.href;
 
+      // Links to other apps, like slack: or spotify:, go through the protocol policy.
+      if (
+        window.pakeConfig.external_protocols &&
+        !/^(https?|blob|data|about|javascript|file|tauri|asset):$/.test(
+          hrefUrl.protocol,
+        )
+      ) {
+        e.preventDefault();
+        invoke('open_external_protocol', { url: absoluteUrl });
+        return;
+      }
+
+      if (
+        window.pakeConfig.external_links_in_browser &&
+        /^(mailto|tel):$/.test(hrefUrl.protocol)
+      ) {
+        e.preventDefault();
+        tauri.shell.open(absoluteUrl);
+        return;
+      }
+
+      // Files the page made itself, like a CSV export.
+      if (
+        isBlobOrDataUrl(absoluteUrl) &&
+        (anchorElement.download || window.blobToUrlCaches.has(absoluteUrl))
+      ) {
+        e.preventDefault();
+        downloadFromBlobUrl(
+          absoluteUrl,
+          anchorElement.download || getFilenameFromUrl(absoluteUrl),
+        ).catch(() => {});
+        return;
+      }
+
+      // App links, subdomains included, that ask for a new window can get one inside the app.
+      if (
+        window.pakeConfig.open_blank_in_new_window &&
+        (target === '_blank' || target === '_new') &&
+        isAppHost(hrefUrl)
+      ) {
+        e.preventDefault();
+        invoke('open_window', { url: absoluteUrl });
+        return;
+      }
+
+      if (
+        window.pakeConfig.new_window_mode &&
+        (target === '_blank' || target === '_new')
+      ) {
+        e.preventDefault();
+        openNewWindow(absoluteUrl);
+        return;
+      }
+
       // Handling external link redirection.
       if (
         window.location.host !== hrefUrl.host &&
-        (target === '_blank' || target === '_new' || externalTargetLink())
+        (target === '_blank' ||
+          target === '_new' ||
+          externalTargetLink() ||
+          ((window.pakeConfig.internal_urls ||
+            window.pakeConfig.external_links_in_browser) &&
+            /^https?:$/.test(hrefUrl.protocol) &&
+            !isInternalUrl(hrefUrl)))
       ) {
         e.preventDefault && e.preventDefault();
         tauri.shell.open(absoluteUrl);
@@ -173,6 +774,23 @@ document.addEventListener('DOMContentLoaded', () => {
             filename,
           },
         });
+        return;
+      }
+
+      let targetUrl = absoluteUrl;
+
+      // Only normalize links that leave the current document.
+      if (
+        window.pakeConfig.normalize_urls &&
+        /^https?:$/.test(hrefUrl.protocol) &&
+        removeUrlHash(absoluteUrl) !== removeUrlHash(window.location.href)
+      ) {
+        targetUrl = normalizeUrl(absoluteUrl);
+      }
+
+      if (targetUrl !== anchorElement.href) {
+        e.preventDefault();
+        window.location.href = targetUrl;
       }
     }
   };
@@ -180,6 +798,151 @@ document.addEventListener('DOMContentLoaded', () => {
   // Prevent some special websites from executing in advance, before the click event is triggered.
   document.addEventListener('click', detectAnchorElementClick, true);
 
+  // Hand the page its own styles back, and drop a snapshot taken of another site version.
+  if (appearanceSnapshotApplied) {
+    const root = document.documentElement;
+    root.style.backgroundColor = '';
+    root.style.direction = '';
+    root.style.colorScheme = '';
+    if (window.pakeState.appearance.icon_hash !== getIconHash()) {
+      saveAppearance();
+    }
+  }
+  window.addEventListener('load', () => setTimeout(saveAppearance, 3000));
+
+  // Visited pages feed the quick switcher.
+  window.addEventListener('load', () =>
+    invoke('record_visit', {
+      url: window.location.href,
+      title: document.title,
+    }).catch(() => {}),
+  );
+
+  const macros = Object.entries(window.pakeConfig.macros || {}).filter(
+    ([, macro]) => macro.shortcut,
+  );
+  if (macros.length) {
+    document.addEventListener('keydown', (event) => {
+      const match = macros.find(([, macro]) =>
+        matchesShortcut(event, macro.shortcut),
+      );
+      if (match) {
+        event.preventDefault();
+        runMacro(match[0]).catch(() => {});
+      }
+    });
+  }
+
+  const resizeStep = window.pakeConfig.resize_step;
+  if (resizeStep) {
+    const directions = Object.entries(resizeStep.shortcuts).filter(
+      ([, shortcut]) => shortcut,
+    );
+    document.addEventListener('keydown', (event) => {
+      const match = directions.find(([, shortcut]) =>
+        matchesShortcut(event, shortcut),
+      );
+      if (match) {
+        event.preventDefault();
+        invoke('resize_step', { direction: match[0] }).catch(() => {});
+      }
+    });
+  }
+
+  // A swipe counts when it starts near an edge and moves inwards far enough.
+  const edgeSwipe = window.pakeConfig.edge_swipe;
+  if (edgeSwipe) {
+    const edgeSize = 30;
+    const minDistance = 80;
+    let swipeStart = null;
+    document.addEventListener(
+      'touchstart',
+      (event) => {
+        const touch = event.touches[0];
+        const edges = {
+          left: touch.clientX <= edgeSize,
+          right: touch.clientX >= window.innerWidth - edgeSize,
+          top: touch.clientY <= edgeSize,
+          bottom: touch.clientY >= window.innerHeight - edgeSize,
+        };
+        const edge = Object.keys(edges).find((edge) => edges[edge]);
+        swipeStart = edge ? { edge, x: touch.clientX, y: touch.clientY } : null;
+      },
+      { passive: true },
+    );
+    document.addEventListener('touchend', (event) => {
+      if (!swipeStart) {
+        return;
+      }
+      const touch = event.changedTouches[0];
+      const distance = {
+        left: touch.clientX - swipeStart.x,
+        right: swipeStart.x - touch.clientX,
+        top: touch.clientY - swipeStart.y,
+        bottom: swipeStart.y - touch.clientY,
+      }[swipeStart.edge];
+      const command = edgeSwipe[swipeStart.edge];
+      swipeStart = null;
+      if (command && distance >= minDistance) {
+        invoke(command).catch(() => {});
+      }
+    });
+  }
+
+  // PWAs describe themselves in their manifest, the app takes its name and icon from there.
+  const manifestLink = document.querySelector('link[rel=manifest]');
+  if (
+    window.pakeConfig.read_web_manifest &&
+    appWindow.label === 'pake' &&
+    manifestLink
+  ) {
+    fetch(manifestLink.href)
+      .then((response) => response.json())
+      .then((manifest) => {
+        const icons = manifest.icons || [];
+        const icon =
+          icons.find((icon) => (icon.sizes || '').split(' ').includes('512x512')) ||
+          icons[icons.length - 1];
+        return invoke('apply_web_manifest', {
+          params: {
+            name: manifest.name || manifest.short_name || null,
+            icon_url: icon ? new URL(icon.src, manifestLink.href).href : null,
+          },
+        });
+      })
+      .catch(() => {});
+  }
+
+  // Widget-style apps size the window to their content, until the user resizes it.
+  if (window.pakeConfig.windows[0].auto_fit && appWindow.label === 'pake') {
+    let fitTimer = null;
+    window.pakeAutoFit = () => {
+      clearTimeout(fitTimer);
+      fitTimer = setTimeout(() => {
+        const root = document.documentElement;
+        const zoom = parseInt(window.localStorage.getItem('htmlZoom') || '100%');
+        invoke('fit_window', {
+          width: root.scrollWidth,
+          height: root.scrollHeight,
+          zoom: zoom / 100,
+        }).catch(() => {});
+      }, 100);
+    };
+    new ResizeObserver(window.pakeAutoFit).observe(document.body);
+    window.addEventListener('load', window.pakeAutoFit);
+  }
+
+  // Pages can delay the quit with event.detail.waitUntil(promise) to flush their storage.
+  tauri.event.listen('pake-before-quit', () => {
+    const pending = [saveAppearance()];
+    window.dispatchEvent(
+      new CustomEvent('pake-before-quit', {
+        detail: { waitUntil: (promise) => pending.push(promise) },
+      }),
+    );
+    Promise.allSettled(pending).then(() => invoke('quit_ready'));
+  });
+
   collectUrlToBlobs();
   detectDownloadByCreateAnchor();
 
@@ -189,17 +952,31 @@ document.addEventListener('DOMContentLoaded', () => {
     // Apple login and google login
     if (name === 'AppleAuthentication') {
       //do nothing
+    } else if (window.pakeConfig.new_window_mode) {
+      const baseUrl = window.location.origin + window.location.pathname;
+      openNewWindow(new URL(url, baseUrl).href, specs || '');
     } else if (specs.includes('height=') || specs.includes('width=')) {
       location.href = url;
     } else {
       const baseUrl = window.location.origin + window.location.pathname;
       const hrefUrl = new URL(url, baseUrl);
-      tauri.shell.open(hrefUrl.href);
+      if (
+        window.pakeConfig.open_blank_in_new_window &&
+        window.location.host === hrefUrl.host
+      ) {
+        invoke('open_window', { url: hrefUrl.href });
+      } else {
+        tauri.shell.open(hrefUrl.href);
+      }
     }
     // Call the original window.open function to maintain its normal functionality.
     return originalWindowOpen.call(window, url, name, specs);
   };
 
+  if (getForceScale()) {
+    applyForceScale(getForceScale());
+  }
+
   // Set the default zoom, There are problems with Loop without using try-catch.
   try {
     setDefaultZoom();
@@ -208,11 +985,35 @@ document.addEventListener('DOMContentLoaded', () => {
   }
 });
 
+// The zoom kept per site in localStorage applies right away, then the one saved by the app wins.
 function setDefaultZoom() {
   const htmlZoom = window.localStorage.getItem('htmlZoom');
   if (htmlZoom) {
-    setZoom(htmlZoom);
+    applyZoom(htmlZoom);
   }
+  invoke('get_zoom')
+    .then((savedZoom) => {
+      if (savedZoom && savedZoom !== 1) {
+        const zoom = `${Math.round(savedZoom * 100)}%`;
+        applyZoom(zoom);
+        window.localStorage.setItem('htmlZoom', zoom);
+      }
+    })
+    .catch(() => {});
+}
+
+// Only the first matching rule applies, so rewritten urls can't loop.
+function applyRewrites(url) {
+  const rewrites = window.pakeConfig.rewrites || [];
+  for (const rewrite of rewrites) {
+    const pattern = new RegExp(rewrite.from_pattern);
+    if (pattern.test(url)) {
+      const rewrittenUrl = url.replace(pattern, rewrite.to_template);
+      invoke('record_rewrite', { params: { from: url, to: rewrittenUrl } });
+      return rewrittenUrl;
+    }
+  }
+  return url;
 }
 
 function getFilenameFromUrl(url) {
@@ -221,12 +1022,46 @@ function getFilenameFromUrl(url) {
   return filename;
 }
 
+function removeUrlHash(url) {
+  const parsedUrl = new URL(url);
+  parsedUrl.hash = '';
+  return parsedUrl.toString();
+}
+
+// Parsing already lowercases the host and drops default ports.
+function normalizeUrl(url) {
+  const parsedUrl = new URL(url);
+  parsedUrl.hash = '';
+  parsedUrl.searchParams.sort();
+  return parsedUrl.toString();
+}
+
 function removeUrlParameters(url) {
   const parsedUrl = new URL(url);
   parsedUrl.search = '';
   return parsedUrl.toString();
 }
 
+// Run the page's beforeunload handlers the way a browser would before closing.
+function pakeCheckBeforeUnload() {
+  const handler = window.onbeforeunload;
+  const event = new Event('beforeunload', { cancelable: true });
+  let blocked = false;
+
+  if (handler) {
+    const result = handler.call(window, event);
+    blocked = result !== null && result !== undefined;
+    // Keep the handler from running twice while dispatching to the listeners.
+    window.onbeforeunload = null;
+  }
+  window.dispatchEvent(event);
+  window.onbeforeunload = handler;
+
+  invoke('before_unload_result', {
+    blocked: blocked || event.defaultPrevented,
+  });
+}
+
 // Toggle video playback when the window is hidden.
 function toggleVideoPlayback(pause) {
   const videos = document.getElementsByTagName('video');
@@ -250,28 +1085,97 @@ function collectUrlToBlobs() {
   };
 }
 
-function convertBlobUrlToBinary(blobUrl) {
-  return new Promise((resolve) => {
-    const blob = window.blobToUrlCaches.get(blobUrl);
-    const reader = new FileReader();
+const BLOB_CHUNK_SIZE = 1024 * 1024;
 
-    reader.readAsArrayBuffer(blob);
-    reader.onload = () => {
-      resolve(reader.result);
-    };
+function readChunkAsBase64(chunk) {
+  return new Promise((resolve, reject) => {
+    const reader = new FileReader();
+    reader.onload = () => resolve(reader.result.split(',')[1] || '');
+    reader.onerror = () => reject(reader.error);
+    reader.readAsDataURL(chunk);
   });
 }
 
-function downloadFromBlobUrl(blobUrl, filename) {
-  const tauri = window.__TAURI__;
-  convertBlobUrlToBinary(blobUrl).then((binary) => {
-    console.log('binary', binary);
-    tauri.fs.writeBinaryFile(filename, binary, {
-      dir: tauri.fs.BaseDirectory.Download,
-    }).then(() => {
-      window.pakeToast('Download successful, saved to download directory~');
-    });
-  });
+// Files made by the page are sent in chunks, so large ones don't freeze it.
+async function downloadFromBlobUrl(blobUrl, filename) {
+  const blob =
+    window.blobToUrlCaches.get(blobUrl) ||
+    (await fetch(blobUrl).then((response) => response.blob()));
+  const id = await invoke('start_blob_download', { filename, size: blob.size });
+  if (id === null) {
+    return;
+  }
+  await sendBlobInChunks(id, blob);
+}
+
+async function sendBlobInChunks(id, blob) {
+  try {
+    for (let start = 0; start < blob.size; start += BLOB_CHUNK_SIZE) {
+      const data = await readChunkAsBase64(
+        blob.slice(start, start + BLOB_CHUNK_SIZE),
+      );
+      await invoke('write_blob_chunk', { id, data });
+    }
+    await invoke('finish_blob_download', { id, complete: true });
+  } catch (e) {
+    await invoke('finish_blob_download', { id, complete: false });
+    throw e;
+  }
+}
+
+// A webview can't capture its own rendering, so the largest canvas on the page is recorded.
+let pakeRecorder = null;
+
+function pakeRecordingStart(fps) {
+  if (pakeRecorder) {
+    return;
+  }
+  const canvas = [...document.querySelectorAll('canvas')].sort(
+    (a, b) => b.width * b.height - a.width * a.height,
+  )[0];
+  if (!canvas || !window.MediaRecorder) {
+    window.pakeToast && window.pakeToast('Nothing to record on this page');
+    return;
+  }
+  // Safari only records mp4.
+  const options = MediaRecorder.isTypeSupported('video/webm')
+    ? { mimeType: 'video/webm' }
+    : {};
+  const recorder = new MediaRecorder(canvas.captureStream(fps), options);
+  const chunks = [];
+  recorder.ondataavailable = (event) => {
+    if (event.data.size > 0) {
+      chunks.push(event.data);
+    }
+  };
+  recorder.start(1000);
+  pakeRecorder = { recorder, chunks };
+}
+
+function pakeRecordingStop(filename) {
+  if (!pakeRecorder) {
+    return;
+  }
+  const { recorder, chunks } = pakeRecorder;
+  pakeRecorder = null;
+  recorder.onstop = async () => {
+    const blob = new Blob(chunks, { type: recorder.mimeType });
+    const extension = recorder.mimeType.includes('mp4') ? 'mp4' : 'webm';
+    try {
+      const id = await invoke('save_recording', {
+        filename: filename || `recording.${extension}`,
+        size: blob.size,
+      });
+      await sendBlobInChunks(id, blob);
+    } catch (e) {
+      console.error('Failed to save the recording', e);
+    }
+  };
+  recorder.stop();
+}
+
+function isBlobOrDataUrl(url) {
+  return /^(blob|data):/.test(url);
 }
 
 // detect blob download by createElement("a")
@@ -282,13 +1186,89 @@ function detectDownloadByCreateAnchor() {
     const anchorEle = createEle.call(document, el);
 
     // use addEventListener to avoid overriding the original click event.
-    anchorEle.addEventListener('click', () => {
+    // Anchors in the document are already handled by the document click listener.
+    anchorEle.addEventListener('click', (event) => {
       const url = anchorEle.href;
-      if (window.blobToUrlCaches.has(url)) {
-        downloadFromBlobUrl(url, anchorEle.download || getFilenameFromUrl(url));
+      if (
+        !event.defaultPrevented &&
+        isBlobOrDataUrl(url) &&
+        (anchorEle.download || window.blobToUrlCaches.has(url))
+      ) {
+        event.preventDefault();
+        downloadFromBlobUrl(
+          url,
+          anchorEle.download || getFilenameFromUrl(url),
+        ).catch(() => {});
       }
     });
 
     return anchorEle;
   }
 }
+
+// Outside the allowed hours Rust covers the page on every load, and starts the app over once
+// they begin again. nextStart is the local time they do, or null.
+function showUnavailablePage(nextStart) {
+  if (window.self !== window.top) {
+    return;
+  }
+  let page = document.getElementById('pakeUnavailable');
+  if (!page) {
+    page = document.createElement('div');
+    page.id = 'pakeUnavailable';
+    page.setAttribute('role', 'alertdialog');
+    Object.assign(page.style, {
+      position: 'fixed',
+      inset: '0',
+      zIndex: '2147483647',
+      display: 'flex',
+      flexDirection: 'column',
+      alignItems: 'center',
+      justifyContent: 'center',
+      gap: '12px',
+      background: 'rgb(255, 255, 255)',
+      color: 'rgb(0, 0, 0)',
+      font: '15px system-ui, sans-serif',
+    });
+    const message = document.createElement('div');
+    message.id = 'pakeUnavailableMessage';
+    const passphrase = document.createElement('input');
+    passphrase.type = 'password';
+    passphrase.placeholder = 'Admin passphrase';
+    const unlock = document.createElement('button');
+    unlock.textContent = 'Unlock';
+    unlock.addEventListener('click', () =>
+      invoke(
+        'override_allowed_hours',
+        { passphrase: passphrase.value },
+        { shakeOnError: true },
+      )
+        .then(() => page.remove())
+        .catch((e) => window.pakeToast && window.pakeToast(e)),
+    );
+    page.append(message, passphrase, unlock);
+    document.body.appendChild(page);
+  }
+  document.getElementById('pakeUnavailableMessage').textContent = nextStart
+    ? `This app is available again ${new Date(nextStart).toLocaleString([], {
+        weekday: 'long',
+        hour: '2-digit',
+        minute: '2-digit',
+      })}`
+    : "This app isn't available right now";
+}
+
+window.pakeShowUnavailable = showUnavailablePage;
+
+// The cover takes the clicks, keys can't reach the page behind it either.
+document.addEventListener(
+  'keydown',
+  (event) => {
+    const page = document.getElementById('pakeUnavailable');
+    if (page && !page.contains(event.target)) {
+      event.preventDefault();
+      event.stopImmediatePropagation();
+    }
+  },
+  true,
+);
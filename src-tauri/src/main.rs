@@ -6,18 +6,106 @@
 mod app;
 mod util;
 
-use app::{invoke, menu, window};
-use invoke::download_file;
+use app::state::StateStore;
+use app::{allowed_hours, instance, invoke, menu, trace, window};
+use invoke::{
+    apply_web_manifest, before_unload_result, cancel_download, close_panel, close_shortcut,
+    close_window, console_error, copy_current_url, download_file, find, find_clear, find_next,
+    finish_blob_download, fit_window, get_console_errors, get_downloads, get_feature_flags,
+    get_info, get_notification_permission, get_rewrite_log, get_switcher_items, get_zoom, go_back,
+    go_forward, hard_reload, hide_window, is_storage_persisted, minimize, notify,
+    open_downloaded_file, open_external_protocol, open_panel, open_popup, open_window,
+    override_allowed_hours, print_page, quit_ready, record_rewrite, record_visit, recording_start,
+    recording_stop, refit_window, reload, request_notification_permission,
+    request_persistent_storage, reset_permissions, resize_step, reveal_in_folder, save_appearance,
+    save_recording, save_zoom, set_a11y_mode, set_feature_flag, set_scale, set_title, set_zoom,
+    shake, start_blob_download, toggle_always_on_top, toggle_downloads_panel, toggle_switcher,
+    ui_degraded, write_blob_chunk,
+};
 use menu::{get_menu, menu_event_handle};
+use tauri::{Invoke, Manager, Wry};
 use tauri_plugin_window_state::Builder as windowStatePlugin;
-use util::{get_data_dir, get_pake_config};
-use window::get_window;
+use util::{get_data_dir, get_pake_config, set_cpu_affinity};
+use window::{get_window, request_close_main_window};
 
 pub fn run_app() {
+    trace::start_trace();
     let (pake_config, tauri_config) = get_pake_config();
+    trace::record("config", || {
+        trace::redact(serde_json::to_value(&pake_config).unwrap())
+    });
+    set_cpu_affinity(&pake_config.cpu_affinity);
     let show_menu = pake_config.show_menu();
     let menu = get_menu();
-    let data_dir = get_data_dir(tauri_config);
+    let data_dir = get_data_dir(&tauri_config);
+    let instance_lock = instance::acquire_instance_lock(&data_dir);
+
+    // Wrapped below, so the trace can see every command.
+    let handler: fn(Invoke<Wry>) = tauri::generate_handler![
+        apply_web_manifest,
+        before_unload_result,
+        cancel_download,
+        close_panel,
+        close_shortcut,
+        close_window,
+        console_error,
+        copy_current_url,
+        download_file,
+        find,
+        find_clear,
+        find_next,
+        finish_blob_download,
+        fit_window,
+        get_console_errors,
+        get_downloads,
+        get_feature_flags,
+        get_info,
+        get_notification_permission,
+        get_rewrite_log,
+        get_switcher_items,
+        get_zoom,
+        go_back,
+        go_forward,
+        hard_reload,
+        hide_window,
+        is_storage_persisted,
+        minimize,
+        notify,
+        open_downloaded_file,
+        open_external_protocol,
+        open_panel,
+        open_popup,
+        open_window,
+        override_allowed_hours,
+        print_page,
+        quit_ready,
+        record_rewrite,
+        record_visit,
+        recording_start,
+        recording_stop,
+        refit_window,
+        reload,
+        request_notification_permission,
+        request_persistent_storage,
+        reset_permissions,
+        resize_step,
+        reveal_in_folder,
+        save_appearance,
+        save_recording,
+        save_zoom,
+        set_a11y_mode,
+        set_feature_flag,
+        set_scale,
+        set_title,
+        set_zoom,
+        shake,
+        start_blob_download,
+        toggle_always_on_top,
+        toggle_downloads_panel,
+        toggle_switcher,
+        ui_degraded,
+        write_blob_chunk
+    ];
 
     let mut tauri_app = tauri::Builder::default();
 
@@ -41,25 +129,95 @@ pub fn run_app() {
 
     tauri_app
         .plugin(windowStatePlugin::default().build())
-        .invoke_handler(tauri::generate_handler![download_file])
+        .manage(StateStore::load(&data_dir))
+        .invoke_handler(move |invoke| {
+            trace::record_invoke(&invoke.message);
+            // Popups can show any site, so they get none of the app's commands.
+            if window::is_popup_window(invoke.message.window_ref().label()) {
+                invoke.resolver.reject("Popups can't call app commands");
+                return;
+            }
+            if allowed_hours::is_outside_allowed_hours()
+                && !allowed_hours::OUTSIDE_HOURS_COMMANDS.contains(&invoke.message.command())
+            {
+                invoke.resolver.reject(allowed_hours::OUTSIDE_HOURS_MESSAGE);
+                return;
+            }
+            handler(invoke)
+        })
+        .on_page_load(|window, payload| {
+            trace::record(
+                "page-load",
+                || serde_json::json!({ "window": window.label(), "url": payload.url() }),
+            );
+            if window.label() == "pake" {
+                trace::replay(&window);
+                allowed_hours::cover_page(&window);
+            }
+        })
         .setup(|app| {
-            let _window = get_window(app, pake_config, data_dir);
+            let _window = get_window(app, &pake_config, data_dir);
             // Prevent initial shaking
             _window.show().unwrap();
+            if pake_config.media_keys {
+                window::register_media_keys(app);
+            }
+            #[cfg(target_os = "windows")]
+            if !pake_config.jump_list.is_empty() {
+                app::jump_list::set_jump_list(&pake_config.jump_list);
+            }
+            let restore_session = pake_config.restore_session;
+            app.manage(pake_config);
+            instance::listen_for_instances(instance_lock, app.handle());
+            window::watch_monitors(app.handle());
+            allowed_hours::watch_allowed_hours(app.handle());
+            if restore_session {
+                window::restore_session(&app.handle());
+            }
             Ok(())
         })
         .on_window_event(|event| {
-            if let tauri::WindowEvent::CloseRequested { api, .. } = event.event() {
-                #[cfg(target_os = "macos")]
-                {
-                    event.window().minimize().unwrap();
-                    // event.window().eval("toggleVideoPlayback(true);").unwrap();
+            trace::record("window-event", || {
+                serde_json::json!({
+                    "window": event.window().label(),
+                    "event": format!("{:?}", event.event()),
+                })
+            });
+
+            if matches!(
+                event.event(),
+                tauri::WindowEvent::Resized(_) | tauri::WindowEvent::Moved(_)
+            ) && event.window().label() == "pake"
+            {
+                window::schedule_save_window_state(event.window().app_handle());
+            }
+
+            if let tauri::WindowEvent::Resized(size) = event.event() {
+                if event.window().label() == "pake" {
+                    window::note_main_window_resize(*size);
                 }
+            }
 
-                #[cfg(not(target_os = "macos"))]
-                event.window().close().unwrap();
+            // Briefly pinning the window lifts it above other overlapping Pake apps.
+            if let tauri::WindowEvent::Focused(true) = event.event() {
+                if event
+                    .window()
+                    .state::<app::config::PakeConfig>()
+                    .bring_to_front_on_click
+                    && !window::is_window_pinned(event.window().label())
+                {
+                    let _res = event.window().set_always_on_top(true);
+                    let _res = event.window().set_always_on_top(false);
+                }
+            }
 
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event.event() {
+                // Panels and other secondary windows simply close.
+                if event.window().label() != "pake" {
+                    return;
+                }
                 api.prevent_close();
+                request_close_main_window(event.window());
             }
         })
         .run(tauri::generate_context!())
@@ -1,35 +1,272 @@
-use crate::app::config::PakeConfig;
+use crate::app::config::{AllowedHours, PakeConfig, QuickLink, Rewrite, UserAgent};
+use crate::app::state::HistoryEntry;
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Timelike, Weekday};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
-use tauri::{api, Config, Window};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{api, Config, Url, Window};
 
 pub fn get_pake_config() -> (PakeConfig, Config) {
-    let pake_config: PakeConfig =
-        serde_json::from_str(include_str!("../pake.json")).expect("Failed to parse pake config");
+    let mut pake_config: PakeConfig = get_local_pake_config().unwrap_or_else(|| {
+        serde_json::from_str(include_str!("../pake.json")).expect("Failed to parse pake config")
+    });
 
     let tauri_config: Config = serde_json::from_str(include_str!("../tauri.conf.json"))
         .expect("Failed to parse tauri config");
 
+    if let Some(override_path) = get_override_config_path(&tauri_config) {
+        if let Err(e) = apply_override_config(&mut pake_config, &override_path) {
+            eprintln!(
+                "Ignoring {}, using the embedded config: {}",
+                override_path.display(),
+                e
+            );
+        }
+    }
+
+    apply_cli_args(&mut pake_config, env::args().skip(1));
+
+    // Checked before the app starts, a bad config exits with the reason instead of crashing.
+    if let Err(e) = check_pake_config(&pake_config) {
+        eprintln!("Invalid pake config: {}", e);
+        std::process::exit(1);
+    }
+
     (pake_config, tauri_config)
 }
 
-pub fn get_data_dir(_tauri_config: Config) -> PathBuf {
+fn check_pake_config(config: &PakeConfig) -> Result<(), String> {
+    if let Some(scale) = config
+        .windows
+        .first()
+        .and_then(|window_config| window_config.force_scale)
+    {
+        check_scale(scale)?;
+    }
+    check_rewrites(&config.rewrites)?;
+    check_macros(config)?;
+    if let Some(allowed_hours) = &config.allowed_hours {
+        check_allowed_hours(allowed_hours)?;
+    }
+    if config.open_blank_in_new_window && config.new_window_mode.is_some() {
+        return Err("open_blank_in_new_window can't be combined with new_window_mode".to_string());
+    }
+    Ok(())
+}
+
+const USAGE: &str =
+    "Usage: [--url <url>] [--width <pixels>] [--height <pixels>] [--fullscreen] [--no-restore] \
+     [--trace <file>] [--replay <file>]";
+
+// Command line flags win over every config file.
+fn apply_cli_args(config: &mut PakeConfig, mut args: impl Iterator<Item = String>) {
+    let window_config = config
+        .windows
+        .first_mut()
+        .expect("At least one window configuration is required");
+
+    while let Some(arg) = args.next() {
+        let field = arg.trim_start_matches("--");
+        if matches!(field, "url" | "width" | "height" | "fullscreen")
+            && is_locked(&config.locked_settings, field)
+        {
+            if field != "fullscreen" {
+                args.next();
+            }
+            continue;
+        }
+
+        match arg.as_str() {
+            // A bad url only warns, so a typo still opens the configured page.
+            "--url" => {
+                let url = args.next().unwrap_or_default();
+                if Url::parse(&url).is_err() {
+                    eprintln!(
+                        "Invalid url \"{}\", opening {} instead",
+                        url, window_config.url
+                    );
+                    continue;
+                }
+                window_config.url = url;
+                window_config.url_type = "web".to_string();
+            }
+            "--width" => window_config.width = get_size_arg(&arg, args.next()),
+            "--height" => window_config.height = get_size_arg(&arg, args.next()),
+            "--fullscreen" => window_config.fullscreen = true,
+            "--no-restore" => config.restore_session = false,
+            // Read by the trace module.
+            "--trace" | "--replay" => {
+                args.next();
+            }
+            // Older macOS versions pass a process serial number when launched from Finder.
+            _ if arg.starts_with("-psn_") => {}
+            _ => exit_with_usage(&format!("Unknown argument \"{}\"", arg)),
+        }
+    }
+}
+
+fn get_size_arg(flag: &str, value: Option<String>) -> f64 {
+    match value.as_deref().map(str::parse::<f64>) {
+        Some(Ok(size)) if size > 0.0 => size,
+        _ => exit_with_usage(&format!("{} needs a positive number", flag)),
+    }
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(1);
+}
+
+// PAKE_CONFIG wins over the pake.json in the app's config dir.
+fn get_override_config_path(tauri_config: &Config) -> Option<PathBuf> {
+    if let Some(path) = env::var_os("PAKE_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let package_name = tauri_config.package.product_name.as_ref()?;
+    let config_path = api::path::config_dir()?
+        .join(package_name)
+        .join("pake.json");
+    config_path.exists().then_some(config_path)
+}
+
+// Only the fields worth changing without a rebuild can be overridden.
+fn apply_override_config(config: &mut PakeConfig, path: &Path) -> Result<(), String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut values: Map<String, Value> =
+        serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    values.retain(|field, _| !is_locked(&config.locked_settings, field));
+
+    let url: Option<String> = get_override_field(&values, "url")?;
+    let width: Option<f64> = get_override_field(&values, "width")?;
+    let height: Option<f64> = get_override_field(&values, "height")?;
+    let resizable: Option<bool> = get_override_field(&values, "resizable")?;
+    let fullscreen: Option<bool> = get_override_field(&values, "fullscreen")?;
+    let transparent: Option<bool> = get_override_field(&values, "transparent")?;
+    let user_agent: Option<String> = get_override_field(&values, "user_agent")?;
+
+    let window_config = config
+        .windows
+        .first_mut()
+        .expect("At least one window configuration is required");
+    if let Some(url) = url {
+        window_config.url = url;
+    }
+    if let Some(width) = width {
+        window_config.width = width;
+    }
+    if let Some(height) = height {
+        window_config.height = height;
+    }
+    if let Some(resizable) = resizable {
+        window_config.resizable = resizable;
+    }
+    if let Some(fullscreen) = fullscreen {
+        window_config.fullscreen = fullscreen;
+    }
+    if let Some(transparent) = transparent {
+        window_config.transparent = transparent;
+    }
+    if let Some(user_agent) = user_agent {
+        config.user_agent = UserAgent::Global(user_agent);
+    }
+    Ok(())
+}
+
+fn is_locked(locked_settings: &[String], field: &str) -> bool {
+    let locked = locked_settings.iter().any(|setting| setting == field);
+    if locked {
+        eprintln!("Ignoring the override of {}, it is locked", field);
+    }
+    locked
+}
+
+fn get_override_field<T: DeserializeOwned>(
+    values: &Map<String, Value>,
+    field: &str,
+) -> Result<Option<T>, String> {
+    values
+        .get(field)
+        .map(|value| {
+            serde_json::from_value(value.clone())
+                .map_err(|e| format!("invalid field \"{}\": {}", field, e))
+        })
+        .transpose()
+}
+
+// A pake.conf.json next to the executable replaces the embedded config.
+fn get_local_pake_config() -> Option<PakeConfig> {
+    let config_path = env::current_exe().ok()?.parent()?.join("pake.conf.json");
+    let content = fs::read_to_string(&config_path).ok()?;
+
+    serde_json::from_str(&content)
+        .map_err(|e| {
+            eprintln!(
+                "Failed to parse {}, using the embedded config: {}",
+                config_path.display(),
+                e
+            )
+        })
+        .ok()
+}
+
+static DATA_DIR_CHECKED: AtomicBool = AtomicBool::new(false);
+
+pub fn get_data_dir(_tauri_config: &Config) -> PathBuf {
     {
-        let package_name = _tauri_config.package.product_name.unwrap();
-        let data_dir = api::path::config_dir()
+        let package_name = _tauri_config.package.product_name.as_ref().unwrap();
+        let base_dir = if is_sandboxed() {
+            api::path::data_dir()
+        } else {
+            api::path::config_dir()
+        };
+        let data_dir = base_dir
             .expect("Failed to get data dirname")
             .join(package_name);
 
-        if !data_dir.exists() {
-            std::fs::create_dir(&data_dir)
-                .unwrap_or_else(|_| panic!("Can't create dir {}", data_dir.display()));
+        // Without a writable data dir the app still runs, it just can't remember anything.
+        if !DATA_DIR_CHECKED.swap(true, Ordering::SeqCst) {
+            if let Err(e) = ensure_writable_dir(&data_dir) {
+                eprintln!(
+                    "Can't write to {}, settings won't be saved: {}",
+                    data_dir.display(),
+                    e
+                );
+            }
         }
         data_dir
     }
 }
 
+// Windows and panels may create the directory at the same time, create_dir_all is fine with that.
+fn ensure_writable_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe_path = dir.join(".pake-probe");
+    fs::write(&probe_path, b"")?;
+    fs::remove_file(probe_path)
+}
+
+// PAKE_DEVTOOLS=1 opens the devtools in release builds too, to look into a user's problem.
+// Only builds with the devtools feature listen to it, the others ship without devtools.
+pub fn devtools_requested() -> bool {
+    cfg!(feature = "devtools") && env::var("PAKE_DEVTOOLS").map_or(false, |value| value == "1")
+}
+
+// Flatpak and Snap only map their own XDG data dir into the sandbox.
+fn is_sandboxed() -> bool {
+    cfg!(target_os = "linux")
+        && (env::var_os("FLATPAK_ID").is_some() || env::var_os("SNAP").is_some())
+}
+
 pub fn show_toast(window: &Window, message: &str) {
-    let script = format!(r#"pakeToast("{}");"#, message);
+    let script = format!("pakeToast({});", serde_json::to_string(message).unwrap());
     window.eval(&script).unwrap();
 }
 
@@ -49,30 +286,714 @@ pub fn get_download_message() -> String {
         .to_string()
 }
 
-// Check if the file exists, if it exists, add a number to file name
+// Check if the file exists, if it exists, number it like "report (1).pdf"
+// A name that is numbered already counts on, so "report (1).pdf" goes to "report (2).pdf".
 pub fn check_file_or_append(file_path: &str) -> String {
-    let mut new_path = PathBuf::from(file_path);
-    let mut counter = 0;
-
-    while new_path.exists() {
-        let file_stem = new_path.file_stem().unwrap().to_string_lossy().to_string();
-        let extension = new_path.extension().unwrap().to_string_lossy().to_string();
-        let parent_dir = new_path.parent().unwrap();
-
-        let new_file_stem = match file_stem.rfind('-') {
-            Some(index) if file_stem[index + 1..].parse::<u32>().is_ok() => {
-                let base_name = &file_stem[..index];
-                counter = file_stem[index + 1..].parse::<u32>().unwrap() + 1;
-                format!("{}-{}", base_name, counter)
+    let path = PathBuf::from(file_path);
+    if !path.exists() {
+        return file_path.to_string();
+    }
+
+    let parent_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let (file_stem, mut counter) = file_stem
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .filter(|(_, counter)| !counter.is_empty() && counter.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|(base, counter)| Some((base, counter.parse::<u32>().ok()? + 1)))
+        .unwrap_or((file_stem.as_ref(), 1));
+    loop {
+        let new_path = parent_dir.join(format!("{} ({}){}", file_stem, counter, extension));
+        if !new_path.exists() {
+            return new_path.to_string_lossy().into_owned();
+        }
+        counter += 1;
+    }
+}
+
+// Windows won't create files with these names, whatever their extension.
+const RESERVED_FILENAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Names come from pages and servers, keep them from leaving the download directory.
+// Leading dots go so nothing lands hidden, trailing ones and spaces because Windows drops them.
+pub fn sanitize_filename(filename: &str) -> String {
+    let sanitized: String = filename
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    let stem = sanitized.split('.').next().unwrap_or_default().trim_end();
+    if sanitized.is_empty() {
+        "download".to_string()
+    } else if RESERVED_FILENAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        format!("_{}", sanitized)
+    } else {
+        sanitized.to_string()
+    }
+}
+
+// Keep the forced scale within a range where pages stay usable.
+pub fn check_scale(scale: f64) -> Result<f64, String> {
+    if (0.25..=4.0).contains(&scale) {
+        Ok(scale)
+    } else {
+        Err(format!("Scale {} must be between 0.25 and 4.0", scale))
+    }
+}
+
+// Reject templates that refer to capture groups their pattern doesn't have.
+pub fn check_rewrites(rewrites: &[Rewrite]) -> Result<(), String> {
+    let group_ref = Regex::new(r"\$(\d+)|\$\{(\d+)\}").unwrap();
+    for rewrite in rewrites {
+        let pattern = Regex::new(&rewrite.from_pattern)
+            .map_err(|e| format!("Invalid rewrite pattern {}: {}", rewrite.from_pattern, e))?;
+        for caps in group_ref.captures_iter(&rewrite.to_template) {
+            let group = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+            if group.parse::<usize>().unwrap_or(usize::MAX) >= pattern.captures_len() {
+                return Err(format!(
+                    "Rewrite template {} refers to missing group ${} of {}",
+                    rewrite.to_template, group, rewrite.from_pattern
+                ));
             }
-            _ => {
-                counter += 1;
-                format!("{}-{}", file_stem, counter)
+        }
+    }
+    Ok(())
+}
+
+const MAX_MACRO_STEPS: usize = 16;
+// Commands a macro may run. run_macro isn't one of them, so macros can't nest.
+const MACRO_COMMANDS: &[&str] = &[
+    "close_panel",
+    "copy_current_url",
+    "find",
+    "find_clear",
+    "find_next",
+    "go_back",
+    "go_forward",
+    "hard_reload",
+    "hide_window",
+    "minimize",
+    "notify",
+    "open_panel",
+    "open_window",
+    "print_page",
+    "recording_start",
+    "recording_stop",
+    "reload",
+    "resize_step",
+    "set_a11y_mode",
+    "set_scale",
+    "set_title",
+    "set_zoom",
+    "shake",
+    "toggle_always_on_top",
+    "toggle_downloads_panel",
+    "toggle_switcher",
+];
+
+// Catch typos in command names and clashing shortcuts at startup, rather than halfway through
+// a macro or when a key does something else than expected.
+pub fn check_macros(config: &PakeConfig) -> Result<(), String> {
+    let mut names: Vec<&String> = config.macros.keys().collect();
+    names.sort();
+    let mut shortcuts: Vec<(String, String)> = Vec::new();
+    if let Some(omnibox) = config.omnibox.as_ref().filter(|omnibox| omnibox.enabled) {
+        shortcuts.push((
+            normalize_shortcut(&omnibox.shortcut),
+            "the omnibox".to_string(),
+        ));
+    }
+    if let Some(resize_step) = &config.resize_step {
+        let resize_shortcuts = &resize_step.shortcuts;
+        for shortcut in [
+            &resize_shortcuts.larger,
+            &resize_shortcuts.smaller,
+            &resize_shortcuts.wider,
+            &resize_shortcuts.taller,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            shortcuts.push((normalize_shortcut(shortcut), "resize_step".to_string()));
+        }
+    }
+
+    for name in names {
+        let macro_config = &config.macros[name];
+        if macro_config.steps.is_empty() || macro_config.steps.len() > MAX_MACRO_STEPS {
+            return Err(format!(
+                "Macro {} must have between 1 and {} steps",
+                name, MAX_MACRO_STEPS
+            ));
+        }
+        if let Some(step) = macro_config
+            .steps
+            .iter()
+            .find(|step| !MACRO_COMMANDS.contains(&step.command.as_str()))
+        {
+            return Err(format!(
+                "Macro {} uses unknown command {}",
+                name, step.command
+            ));
+        }
+        if let Some(shortcut) = &macro_config.shortcut {
+            let shortcut = normalize_shortcut(shortcut);
+            if let Some((_, owner)) = shortcuts.iter().find(|(taken, _)| *taken == shortcut) {
+                return Err(format!("Macro {} uses the shortcut of {}", name, owner));
             }
-        };
+            shortcuts.push((shortcut, format!("macro {}", name)));
+        }
+    }
+    Ok(())
+}
+
+// Accelerators like event.js reads them, so "Shift+Control+K" and "ctrl+shift+k" are the same.
+fn normalize_shortcut(shortcut: &str) -> String {
+    let shortcut = shortcut.to_lowercase();
+    let mut parts: Vec<&str> = shortcut
+        .split('+')
+        .map(|part| match part.trim() {
+            "command" => "cmd",
+            "control" => "ctrl",
+            "option" => "alt",
+            "commandorcontrol" => "cmdorctrl",
+            part => part,
+        })
+        .collect();
+    let key = parts.pop().unwrap_or_default();
+    parts.sort_unstable();
+    parts.dedup();
+    parts.push(key);
+    parts.join("+")
+}
+
+// Flags changed with set_feature_flag, they win over the config until the app quits.
+static FEATURE_FLAG_CHANGES: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
 
-        new_path = parent_dir.join(format!("{}.{}", new_file_stem, extension));
+pub fn read_feature_flags(config: &PakeConfig) -> HashMap<String, bool> {
+    let mut flags = config.feature_flags.clone();
+    flags.extend(FEATURE_FLAG_CHANGES.lock().unwrap().iter().cloned());
+    flags
+}
+
+// Only configured flags can change, pages can't make up new ones.
+pub fn store_feature_flag(config: &PakeConfig, key: &str, enabled: bool) -> Result<(), String> {
+    if !config.feature_flags.contains_key(key) {
+        return Err(format!("Unknown feature flag {}", key));
+    }
+    let mut changes = FEATURE_FLAG_CHANGES.lock().unwrap();
+    changes.retain(|(changed_key, _)| changed_key != key);
+    changes.push((key.to_string(), enabled));
+    Ok(())
+}
+
+const WEEKDAYS: &[&str] = &["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+pub fn check_allowed_hours(allowed_hours: &AllowedHours) -> Result<(), String> {
+    for time in [&allowed_hours.start, &allowed_hours.end] {
+        let valid = time.split_once(':').map_or(false, |(hours, minutes)| {
+            hours.len() == 2
+                && minutes.len() == 2
+                && hours.parse::<u32>().map_or(false, |hours| hours < 24)
+                && minutes.parse::<u32>().map_or(false, |minutes| minutes < 60)
+        });
+        if !valid {
+            return Err(format!("Allowed hours need times like 08:00, not {}", time));
+        }
     }
+    if allowed_hours.start == allowed_hours.end {
+        return Err("Allowed hours must not start and end at the same time".to_string());
+    }
+    if let Some(day) = allowed_hours
+        .days
+        .iter()
+        .find(|day| !WEEKDAYS.contains(&day.as_str()))
+    {
+        return Err(format!("Unknown day {} in allowed hours", day));
+    }
+    Ok(())
+}
+
+// Minutes since midnight, for times that passed check_allowed_hours.
+fn parse_time_of_day(time: &str) -> u32 {
+    let (hours, minutes) = time.split_once(':').unwrap_or_default();
+    hours.parse::<u32>().unwrap_or(0) * 60 + minutes.parse::<u32>().unwrap_or(0)
+}
 
-    new_path.to_string_lossy().into_owned()
+fn is_allowed_day(allowed_hours: &AllowedHours, weekday: Weekday) -> bool {
+    let day = WEEKDAYS[weekday.num_days_from_monday() as usize];
+    allowed_hours.days.is_empty() || allowed_hours.days.iter().any(|allowed| allowed == day)
+}
+
+// Local times, an end before the start belongs to the day the hours started.
+pub fn is_within_allowed_hours(allowed_hours: &AllowedHours, now: NaiveDateTime) -> bool {
+    let start = parse_time_of_day(&allowed_hours.start);
+    let end = parse_time_of_day(&allowed_hours.end);
+    let minutes = now.hour() * 60 + now.minute();
+    let today = now.weekday();
+    if start < end {
+        return is_allowed_day(allowed_hours, today) && minutes >= start && minutes < end;
+    }
+    (is_allowed_day(allowed_hours, today) && minutes >= start)
+        || (is_allowed_day(allowed_hours, today.pred()) && minutes < end)
+}
+
+pub fn get_next_allowed_start(
+    allowed_hours: &AllowedHours,
+    now: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    let start = parse_time_of_day(&allowed_hours.start);
+    let start = NaiveTime::from_hms_opt(start / 60, start % 60, 0)?;
+    (0..=7)
+        .map(|offset| (now.date() + chrono::Duration::days(offset)).and_time(start))
+        .find(|day| *day > now && is_allowed_day(allowed_hours, day.weekday()))
+}
+
+// Pin the process to the configured CPUs, failures only get logged.
+pub fn set_cpu_affinity(cpus: &[u32]) {
+    if cpus.is_empty() {
+        return;
+    }
+    match apply_cpu_affinity(cpus) {
+        Ok(()) => println!("Pinned to CPUs {:?}", cpus),
+        Err(e) => eprintln!("Failed to pin to CPUs {:?}: {}", cpus, e),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn apply_cpu_affinity(cpus: &[u32]) -> Result<(), String> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            if cpu as usize >= libc::CPU_SETSIZE as usize {
+                return Err(format!("CPU {} is out of range", cpu));
+            }
+            libc::CPU_SET(cpu as usize, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn apply_cpu_affinity(cpus: &[u32]) -> Result<(), String> {
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, SetProcessAffinityMask};
+
+    let mut mask: usize = 0;
+    for &cpu in cpus {
+        if cpu >= usize::BITS {
+            return Err(format!("CPU {} is out of range", cpu));
+        }
+        mask |= 1 << cpu;
+    }
+    if unsafe { SetProcessAffinityMask(GetCurrentProcess(), mask) } == 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+// macOS only takes affinity hints per thread, and ignores them on Apple silicon.
+#[cfg(target_os = "macos")]
+fn apply_cpu_affinity(_cpus: &[u32]) -> Result<(), String> {
+    Err("CPU affinity is not supported on macOS".to_string())
+}
+
+// The configured site and its subdomains, with or without www.
+// isAppHost in event.js follows the same rule, keep them in step.
+pub fn is_same_host(config: &PakeConfig, url: &Url) -> bool {
+    let window_config = config
+        .windows
+        .first()
+        .expect("At least one window configuration is required");
+
+    match (window_config.url.parse::<Url>(), url.host_str()) {
+        (Ok(base_url), Some(host)) => base_url
+            .host_str()
+            .map_or(false, |app_host| is_app_host(app_host, host)),
+        _ => false,
+    }
+}
+
+fn is_app_host(app_host: &str, host: &str) -> bool {
+    let app_host = app_host.strip_prefix("www.").unwrap_or(app_host);
+    host == app_host || host.ends_with(&format!(".{}", app_host))
+}
+
+// Limits how often a fast-changing value is applied. Repeats of the last applied value are
+// dropped, and values pushed too soon are held back with the latest one winning.
+// The time is passed in, so the behaviour doesn't depend on a real clock.
+pub struct Coalescer<T> {
+    interval: Duration,
+    last_applied: Option<(T, Instant)>,
+    pending: Option<T>,
+}
+
+impl<T: Clone + PartialEq> Coalescer<T> {
+    pub const fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_applied: None,
+            pending: None,
+        }
+    }
+
+    // Returns the value when it should be applied right away.
+    pub fn push(&mut self, value: T, now: Instant) -> Option<T> {
+        match &self.last_applied {
+            Some((last_value, _)) if *last_value == value => {
+                self.pending = None;
+                None
+            }
+            Some((_, applied_at)) if now.duration_since(*applied_at) < self.interval => {
+                self.pending = Some(value);
+                None
+            }
+            _ => self.apply(value, now),
+        }
+    }
+
+    // Returns the value held back by push, to apply once the interval has passed.
+    pub fn flush(&mut self, now: Instant) -> Option<T> {
+        let value = self.pending.take()?;
+        self.apply(value, now)
+    }
+
+    fn apply(&mut self, value: T, now: Instant) -> Option<T> {
+        self.pending = None;
+        self.last_applied = Some((value.clone(), now));
+        Some(value)
+    }
+}
+
+const SWITCHER_ITEM_LIMIT: usize = 20;
+
+#[derive(Serialize)]
+pub struct SwitcherItem {
+    pub title: String,
+    pub url: String,
+    pub quick_link: bool,
+}
+
+// Quick links come first, then the history ranked by frecency.
+pub fn rank_switcher_items(
+    quick_links: &[QuickLink],
+    history: &[HistoryEntry],
+    query: &str,
+    now: u64,
+) -> Vec<SwitcherItem> {
+    let query = query.to_lowercase();
+    let matches = |title: &str, url: &str| {
+        title.to_lowercase().contains(&query) || url.to_lowercase().contains(&query)
+    };
+
+    let mut entries: Vec<&HistoryEntry> = history
+        .iter()
+        .filter(|entry| matches(&entry.title, &entry.url))
+        .filter(|entry| !quick_links.iter().any(|link| link.url == entry.url))
+        .collect();
+    entries.sort_by(|a, b| frecency(b, now).total_cmp(&frecency(a, now)));
+
+    quick_links
+        .iter()
+        .filter(|link| matches(&link.title, &link.url))
+        .map(|link| SwitcherItem {
+            title: link.title.clone(),
+            url: link.url.clone(),
+            quick_link: true,
+        })
+        .chain(entries.into_iter().map(|entry| SwitcherItem {
+            title: entry.title.clone(),
+            url: entry.url.clone(),
+            quick_link: false,
+        }))
+        .take(SWITCHER_ITEM_LIMIT)
+        .collect()
+}
+
+// Visit count weighted by how long ago the last visit was.
+fn frecency(entry: &HistoryEntry, now: u64) -> f64 {
+    let weight = match now.saturating_sub(entry.last_visit) / 86400 {
+        0..=3 => 1.0,
+        4..=13 => 0.7,
+        14..=30 => 0.5,
+        31..=89 => 0.3,
+        _ => 0.1,
+    };
+    entry.visits as f64 * weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::config::NewWindowMode;
+
+    // A fresh directory per test, so tests running at the same time don't see each other's files.
+    fn get_test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pake-test-{}-{}", std::process::id(), name));
+        let _res = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn check_in(dir: &Path, filename: &str) -> String {
+        let path = check_file_or_append(&dir.join(filename).to_string_lossy());
+        Path::new(&path)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn free_filenames_are_kept() {
+        let dir = get_test_dir("free");
+        assert_eq!(check_in(&dir, "report.pdf"), "report.pdf");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn taken_filenames_are_numbered() {
+        let dir = get_test_dir("taken");
+        fs::write(dir.join("report.pdf"), "").unwrap();
+        assert_eq!(check_in(&dir, "report.pdf"), "report (1).pdf");
+        fs::write(dir.join("report (1).pdf"), "").unwrap();
+        assert_eq!(check_in(&dir, "report.pdf"), "report (2).pdf");
+        assert_eq!(check_in(&dir, "report (1).pdf"), "report (2).pdf");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filenames_without_an_extension_are_numbered() {
+        let dir = get_test_dir("no-extension");
+        fs::write(dir.join("README"), "").unwrap();
+        fs::write(dir.join(".env"), "").unwrap();
+        fs::write(dir.join("notes (draft)"), "").unwrap();
+        assert_eq!(check_in(&dir, "README"), "README (1)");
+        assert_eq!(check_in(&dir, ".env"), ".env (1)");
+        assert_eq!(check_in(&dir, "notes (draft)"), "notes (draft) (1)");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sanitized_filenames_stay_in_the_directory() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(
+            sanitize_filename("C:\\Windows\\win.ini"),
+            "C__Windows_win.ini"
+        );
+        assert_eq!(sanitize_filename("a\nb?.txt"), "a_b_.txt");
+    }
+
+    #[test]
+    fn sanitized_filenames_are_not_hidden() {
+        assert_eq!(sanitize_filename(".bashrc"), "bashrc");
+        assert_eq!(sanitize_filename("  ..report.pdf. "), "report.pdf");
+    }
+
+    #[test]
+    fn reserved_windows_names_are_prefixed() {
+        assert_eq!(sanitize_filename("CON"), "_CON");
+        assert_eq!(sanitize_filename("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_filename("com1 .tar.gz"), "_com1 .tar.gz");
+        assert_eq!(sanitize_filename("console.log"), "console.log");
+    }
+
+    #[test]
+    fn empty_filenames_get_a_default() {
+        assert_eq!(sanitize_filename(""), "download");
+        assert_eq!(sanitize_filename(" . .. "), "download");
+    }
+
+    #[test]
+    fn app_host_includes_www_and_subdomains() {
+        assert!(is_app_host("www.example.com", "example.com"));
+        assert!(is_app_host("example.com", "www.example.com"));
+        assert!(is_app_host("example.com", "docs.example.com"));
+        assert!(!is_app_host("example.com", "example.com.evil.io"));
+        assert!(!is_app_host("example.com", "notexample.com"));
+        assert!(!is_app_host("docs.example.com", "example.com"));
+    }
+
+    fn get_test_config() -> PakeConfig {
+        serde_json::from_str(include_str!("../pake.json")).unwrap()
+    }
+
+    fn add_macro(config: &mut PakeConfig, name: &str, shortcut: Option<&str>, commands: &[&str]) {
+        let steps = commands
+            .iter()
+            .map(|command| serde_json::json!({ "command": command }))
+            .collect::<Vec<_>>();
+        let macro_config = serde_json::json!({ "shortcut": shortcut, "steps": steps });
+        config.macros.insert(
+            name.to_string(),
+            serde_json::from_value(macro_config).unwrap(),
+        );
+    }
+
+    #[test]
+    fn macros_with_known_commands_pass() {
+        let mut config = get_test_config();
+        add_macro(
+            &mut config,
+            "focus",
+            Some("CmdOrCtrl+Shift+F"),
+            &["minimize"],
+        );
+        add_macro(&mut config, "reset", None, &["set_zoom", "reload"]);
+        assert!(check_macros(&config).is_ok());
+    }
+
+    #[test]
+    fn macros_with_unknown_commands_are_rejected() {
+        let mut config = get_test_config();
+        add_macro(&mut config, "broken", None, &["reload", "relaod"]);
+        assert_eq!(
+            check_macros(&config).unwrap_err(),
+            "Macro broken uses unknown command relaod"
+        );
+        let mut config = get_test_config();
+        add_macro(&mut config, "nested", None, &["run_macro"]);
+        assert!(check_macros(&config).is_err());
+    }
+
+    #[test]
+    fn empty_macros_are_rejected() {
+        let mut config = get_test_config();
+        add_macro(&mut config, "empty", None, &[]);
+        assert!(check_macros(&config).is_err());
+        let mut config = get_test_config();
+        add_macro(&mut config, "long", None, &["reload"; MAX_MACRO_STEPS + 1]);
+        assert!(check_macros(&config).is_err());
+    }
+
+    #[test]
+    fn macro_shortcut_conflicts_are_rejected() {
+        let mut config = get_test_config();
+        add_macro(&mut config, "a", Some("Ctrl+Shift+K"), &["reload"]);
+        add_macro(&mut config, "b", Some("shift+control+k"), &["minimize"]);
+        assert_eq!(
+            check_macros(&config).unwrap_err(),
+            "Macro b uses the shortcut of macro a"
+        );
+
+        let mut config = get_test_config();
+        config.omnibox = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "shortcut": "CmdOrCtrl+L",
+            "search_url": "https://example.com/?q={query}",
+        }))
+        .unwrap();
+        add_macro(
+            &mut config,
+            "links",
+            Some("CommandOrControl+L"),
+            &["reload"],
+        );
+        assert_eq!(
+            check_macros(&config).unwrap_err(),
+            "Macro links uses the shortcut of the omnibox"
+        );
+    }
+
+    #[test]
+    fn feature_flag_changes_win_over_the_config() {
+        let mut config = get_test_config();
+        config.feature_flags.insert("beta".to_string(), false);
+        config.feature_flags.insert("compact".to_string(), true);
+
+        store_feature_flag(&config, "beta", true).unwrap();
+        store_feature_flag(&config, "beta", false).unwrap();
+        store_feature_flag(&config, "beta", true).unwrap();
+        let flags = read_feature_flags(&config);
+        assert_eq!(flags.get("beta"), Some(&true));
+        assert_eq!(flags.get("compact"), Some(&true));
+        assert_eq!(flags.len(), 2);
+    }
+
+    fn get_allowed_hours(start: &str, end: &str, days: &[&str]) -> AllowedHours {
+        AllowedHours {
+            start: start.to_string(),
+            end: end.to_string(),
+            days: days.iter().map(|day| day.to_string()).collect(),
+            hide_to_tray: false,
+            override_passphrase: None,
+        }
+    }
+
+    // 2024-01-01 is a Monday.
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn allowed_hours_within_a_day() {
+        let allowed_hours = get_allowed_hours("08:00", "18:00", &["mon", "tue"]);
+        assert!(is_within_allowed_hours(&allowed_hours, at(1, 8, 0)));
+        assert!(is_within_allowed_hours(&allowed_hours, at(2, 17, 59)));
+        assert!(!is_within_allowed_hours(&allowed_hours, at(1, 18, 0)));
+        assert!(!is_within_allowed_hours(&allowed_hours, at(1, 7, 59)));
+        assert!(!is_within_allowed_hours(&allowed_hours, at(3, 12, 0)));
+    }
+
+    #[test]
+    fn allowed_hours_past_midnight_belong_to_the_start_day() {
+        let allowed_hours = get_allowed_hours("22:00", "02:00", &["fri"]);
+        assert!(is_within_allowed_hours(&allowed_hours, at(5, 23, 0)));
+        assert!(is_within_allowed_hours(&allowed_hours, at(6, 1, 59)));
+        assert!(!is_within_allowed_hours(&allowed_hours, at(6, 23, 0)));
+        assert!(!is_within_allowed_hours(&allowed_hours, at(5, 1, 0)));
+    }
+
+    #[test]
+    fn next_allowed_start_skips_other_days() {
+        let allowed_hours = get_allowed_hours("08:00", "18:00", &["mon"]);
+        assert_eq!(
+            get_next_allowed_start(&allowed_hours, at(1, 19, 0)),
+            Some(at(8, 8, 0))
+        );
+        assert_eq!(
+            get_next_allowed_start(&allowed_hours, at(1, 7, 0)),
+            Some(at(1, 8, 0))
+        );
+        let every_day = get_allowed_hours("08:00", "18:00", &[]);
+        assert_eq!(
+            get_next_allowed_start(&every_day, at(3, 20, 0)),
+            Some(at(4, 8, 0))
+        );
+    }
+
+    #[test]
+    fn unknown_feature_flags_are_rejected() {
+        let config = get_test_config();
+        assert!(store_feature_flag(&config, "made_up", true).is_err());
+        assert!(!read_feature_flags(&config).contains_key("made_up"));
+    }
+
+    #[test]
+    fn new_window_options_are_exclusive() {
+        let mut config = get_test_config();
+        config.open_blank_in_new_window = true;
+        assert!(check_pake_config(&config).is_ok());
+        config.new_window_mode = Some(NewWindowMode::ChildWindow);
+        assert!(check_pake_config(&config).is_err());
+    }
 }